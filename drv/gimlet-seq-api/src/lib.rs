@@ -8,7 +8,7 @@
 
 use derive_idol_err::IdolError;
 use userlib::*;
-use zerocopy::AsBytes;
+use zerocopy::{AsBytes, FromBytes};
 
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, IdolError)]
 pub enum SeqError {
@@ -16,8 +16,27 @@ pub enum SeqError {
     MuxToHostCPUFailed = 2,
     MuxToSPFailed = 3,
     ClockConfigFailed = 4,
+    BadSpiDevice = 5,
+    NotSupported = 6,
+    BadRail = 7,
+    Held = 8,
+    UpdateInProgress = 9,
+    NoUpdateInProgress = 10,
+    UpdateFailed = 11,
+    PgStuck = 12,
+    PgTimeout = 13,
+    BadRegister = 14,
+    ReprogramFailed = 15,
 }
 
+/// Most iCE40 rails that a board config can list. Replies with an entry per
+/// rail are this long whatever the board, with the entries past its last rail
+/// set to `NO_RAIL`.
+pub const MAX_ICE40_RAILS: usize = 8;
+
+/// Fills the entries of a per-rail reply that don't correspond to a rail.
+pub const NO_RAIL: u32 = u32::MAX;
+
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, AsBytes)]
 #[repr(u8)]
 pub enum PowerState {
@@ -28,4 +47,100 @@ pub enum PowerState {
     A0 = 5,
 }
 
+/// How we found the hardware when the sequencer started: from nothing, or
+/// from an earlier life of ours (or of the whole SP) that left it powered.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, AsBytes)]
+#[repr(u8)]
+pub enum BootKind {
+    /// The iCE40's rails were off, so this was a full power-on.
+    Cold = 1,
+    /// The iCE40's rails were already up, so only the SP (or just this
+    /// task) restarted.
+    Warm = 2,
+}
+
+/// Identifies the sequencer FPGA bitstream that we carry, and the design that
+/// the FPGA says it's running; see `get_fpga_version`.
+#[derive(Copy, Clone, Debug, Default, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct FpgaVersion {
+    /// Ident that the embedded bitstream's design should report
+    pub ident: u16,
+    /// Ident that the FPGA actually reports
+    pub fpga_ident: u16,
+    /// CRC-32 of the embedded (compressed) bitstream, which identifies the
+    /// build rather than just the design
+    pub crc: u32,
+}
+
+/// How much of a streamed FPGA bitstream we've been sent; see
+/// `get_fpga_update_progress`.
+#[derive(Copy, Clone, Debug, Default, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct FpgaUpdateProgress {
+    /// Bytes received (and passed on to the FPGA) so far
+    pub received: u32,
+    /// Length of the whole bitstream, as given to `begin_fpga_update`
+    pub total: u32,
+}
+
+/// Longest register name that `get_fpga_reg` can return.
+pub const FPGA_REG_NAME_LEN: usize = 32;
+
+/// One register from the sequencer FPGA's register map; see `get_fpga_reg`.
+#[derive(Copy, Clone, Debug, Default, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct FpgaRegister {
+    /// Address of the register, as taken by `read_fpga_regs`
+    pub addr: u16,
+    /// Width of the register, in bytes
+    pub width: u8,
+    /// How many bytes of `name` are in use
+    pub name_len: u8,
+    /// Name of the register in the register map, in ASCII
+    pub name: [u8; FPGA_REG_NAME_LEN],
+}
+
+/// Raw state of the sequencer FPGA's power and configuration GPIOs, sampled
+/// one right after another; see `get_power_gpio_snapshot`.
+#[derive(Copy, Clone, Debug, Default, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct PowerGpioSnapshot {
+    /// Kernel timestamp (in ms) taken just before sampling
+    pub timestamp: u64,
+    /// Input word of the port with the iCE40 rail PG pins
+    pub pgs: u16,
+    /// Input word of the port with the iCE40 rail enables, which shows the
+    /// level that's actually on each enable pin
+    pub enables: u16,
+    /// Nonzero if CDONE is high
+    pub cdone: u16,
+    /// Nonzero if CRESETB is high
+    pub creset: u16,
+}
+
+/// What the last successful power-up to A0 looked like, for comparing against
+/// one that goes wrong; see `get_last_bringup`.
+#[derive(Copy, Clone, Debug, Default, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct BringupRecord {
+    /// Kernel timestamp (in ms) when we reached A0
+    pub timestamp: u64,
+    /// Time from enable to power-good for each iCE40 rail, in ms, in the
+    /// order that the board config lists them
+    pub pg_latency: [u32; MAX_ICE40_RAILS],
+    /// Nonzero for each iCE40 rail whose power-good was already asserted the
+    /// first time we looked after turning it on, which may mean a stuck PG
+    pub pg_fast: [u8; MAX_ICE40_RAILS],
+    /// CRC-32 of the FPGA bitstream that we were carrying
+    pub bitstream_crc: u32,
+    /// Ident that the FPGA reported
+    pub fpga_ident: u16,
+    /// Nonzero if there has been a successful bring-up to record; if zero,
+    /// everything else is meaningless
+    pub valid: u8,
+    /// Nonzero if we're in A0 now, with the same bitstream and FPGA ident
+    pub matches_current: u8,
+}
+
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
@@ -2,19 +2,27 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use serde::Deserialize;
+use serde::{de::IgnoredAny, Deserialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt::Write;
 use std::{env, fs, path::PathBuf};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
 
-    let fpga_image = fs::read("fpga.bin")?;
+    let fpga_image = fs::read("fpga.bin")
+        .map_err(|e| format!("failed to read fpga.bin: {}", e))?;
+    check_bitstream(&fpga_image)?;
     let compressed = compress(&fpga_image);
 
+    let mut image = bitstream_header(ident()?, &compressed);
+    image.extend_from_slice(&compressed);
+
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
-    fs::write(out.join("fpga.bin.rle"), compressed)?;
+    fs::write(out.join("fpga.bin.rle"), image)?;
     println!("cargo:rerun-if-changed=fpga.bin");
+    println!("cargo:rerun-if-changed=gimlet_regs.json");
 
     let disposition = build_i2c::Disposition::Devices;
 
@@ -25,6 +33,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     fs::write(out.join("gimlet_regs.rs"), regs()?)?;
 
+    let config = build_util::task_config::<BoardConfig>()?;
+    let global = build_util::config::<GlobalConfig>()?;
+    fs::write(out.join("seq_config.rs"), board_config(&config, &global)?)?;
+
     idol::server::build_server_support(
         "../../idl/gimlet-seq.idol",
         "server_stub.rs",
@@ -50,6 +62,8 @@ enum Node {
         inst_name: String,
         lsb: usize,
         msb: usize,
+        #[serde(default)]
+        reset: Option<usize>,
     },
 }
 
@@ -69,6 +83,7 @@ fn regs() -> Result<String, Box<dyn std::error::Error>> {
         &mut output,
         r##"
 #[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Addr {{"##
     )?;
 
@@ -87,6 +102,45 @@ pub enum Addr {{"##
 
     writeln!(&mut output, "}}")?;
 
+    writeln!(
+        &mut output,
+        r##"
+/// Every register in the map, along with its name and width in bytes.
+#[allow(dead_code)]
+pub const REGISTERS: &[(Addr, &str, u8)] = &["##
+    )?;
+
+    let mut end = 0usize;
+    for child in children.iter() {
+        if let Node::Reg {
+            inst_name,
+            addr_offset,
+            regwidth,
+            ..
+        } = child
+        {
+            writeln!(
+                &mut output,
+                "    (Addr::{}, \"{}\", {}),",
+                inst_name,
+                inst_name,
+                regwidth / 8
+            )?;
+            end = end.max(addr_offset + regwidth / 8);
+        }
+    }
+
+    writeln!(&mut output, "];")?;
+
+    writeln!(
+        &mut output,
+        r##"
+/// One past the last address in the map.
+#[allow(dead_code)]
+pub const ADDR_END: u16 = {:#x};"##,
+        end
+    )?;
+
     writeln!(
         &mut output,
         r##"
@@ -126,6 +180,7 @@ pub mod Reg {{
                     inst_name,
                     lsb,
                     msb,
+                    ..
                 } = child
                 {
                     let nbits = *msb - *lsb + 1;
@@ -152,9 +207,434 @@ pub mod Reg {{
 
     writeln!(&mut output, "}}")?;
 
+    // One typed accessor pair per register, so that callers don't each need
+    // their own single-byte buffer (and the chance to get its size wrong).
+    writeln!(&mut output, "\n#[allow(dead_code)]\nimpl SequencerFpga {{")?;
+
+    for child in children.iter() {
+        if let Node::Reg { inst_name, .. } = child {
+            let lower = inst_name.to_lowercase();
+            writeln!(
+                &mut output,
+                r##"
+    /// Reads the {name} register.
+    pub fn read_{lower}(&self) -> Result<u8, spi_api::SpiError> {{
+        let mut byte = [0u8];
+        self.read_bytes(Addr::{name}, &mut byte)?;
+        Ok(byte[0])
+    }}
+
+    /// Writes the {name} register.
+    pub fn write_{lower}(&self, value: u8) -> Result<(), spi_api::SpiError> {{
+        self.write_bytes(Addr::{name}, &[value])
+    }}"##,
+                name = inst_name,
+                lower = lower,
+            )?;
+        }
+    }
+
+    writeln!(&mut output, "}}")?;
+
+    Ok(output)
+}
+
+/// The board-specific half of the sequencer's configuration, from the task's
+/// `config` section in the app.toml.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoardConfig {
+    /// Which of the app's `[config.spi]` entries our `spi_driver` serves
+    spi_global_config: String,
+    seq_spi_device: u8,
+    ice40_spi_device: u8,
+    creset: Pin,
+    cdone: Pin,
+    global_reset: Option<Pin>,
+    uart_tx_enable: Pin,
+    #[serde(default)]
+    fpga_hack_pins: Vec<HackPin>,
+    pg_pull: Pull,
+    pg_samples: u8,
+    power_up_ms: u64,
+    /// Pause between bitstream chunks while programming the iCE40, for boards
+    /// whose configuration SPI needs time to settle
+    #[serde(default)]
+    chunk_delay_ms: u64,
+    rails: Vec<RailConfig>,
+}
+
+/// The part of the app-wide config that we need: just enough of the SPI
+/// configuration to count the devices behind our SPI server.
+#[derive(Debug, Deserialize)]
+struct GlobalConfig {
+    spi: BTreeMap<String, SpiConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpiConfig {
+    devices: BTreeMap<String, IgnoredAny>,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+enum Port {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Pin {
+    port: Port,
+    pin: usize,
+}
+
+impl Pin {
+    fn mask(&self) -> u16 {
+        1 << self.pin
+    }
+
+    fn pinset(&self) -> String {
+        format!("sys_api::Port::{:?}.pin({})", self.port, self.pin)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HackPin {
+    port: Port,
+    pin: usize,
+    high: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RailConfig {
+    name: String,
+    enable: Pin,
+    pg: Pin,
+    settle_ms: u64,
+    critical: bool,
+}
+
+/// Most consecutive power-good samples that a board config may require.
+const MAX_PG_SAMPLES: u8 = 8;
+
+/// Checks the board config for the mistakes that would otherwise only show up
+/// on hardware, and generates the consts that `main.rs` expects from it.
+fn board_config(
+    config: &BoardConfig,
+    global: &GlobalConfig,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // Every pin that we drive or sample, and what for. Enables and PGs are
+    // folded into a mask per port below, which would hide two rails sharing
+    // a pin, so each one is listed (and checked) on its own.
+    let mut pins = vec![
+        ("creset".to_string(), config.creset),
+        ("cdone".to_string(), config.cdone),
+        ("uart_tx_enable".to_string(), config.uart_tx_enable),
+    ];
+    if let Some(pin) = config.global_reset {
+        pins.push(("global_reset".to_string(), pin));
+    }
+    for h in &config.fpga_hack_pins {
+        let pin = Pin {
+            port: h.port,
+            pin: h.pin,
+        };
+        pins.push(("fpga_hack_pins".to_string(), pin));
+    }
+    for rail in &config.rails {
+        pins.push((format!("{} enable", rail.name), rail.enable));
+        pins.push((format!("{} pg", rail.name), rail.pg));
+    }
+    for (i, (role, pin)) in pins.iter().enumerate() {
+        if pin.pin > 15 {
+            return Err(format!("{}: no such pin: {:?}", role, pin).into());
+        }
+        let dup = pins[..i]
+            .iter()
+            .find(|(_, p)| p.port == pin.port && p.pin == pin.pin);
+        if let Some((other, _)) = dup {
+            return Err(format!(
+                "GPIO{:?} pin {} is assigned to both {} and {}",
+                pin.port, pin.pin, other, role
+            )
+            .into());
+        }
+    }
+
+    // With no samples required, every rail would be believed without
+    // looking at its PG at all; and the sequencer only gets about ten polls
+    // in before it gives up on a rail, so too many can never be met.
+    if !(1..=MAX_PG_SAMPLES).contains(&config.pg_samples) {
+        return Err(format!(
+            "pg_samples must be between 1 and {}, not {}",
+            MAX_PG_SAMPLES, config.pg_samples
+        )
+        .into());
+    }
+
+    if config.seq_spi_device == config.ice40_spi_device {
+        return Err("sequencer and iCE40 must be separate SPI devices".into());
+    }
+
+    let spi = global.spi.get(&config.spi_global_config).ok_or_else(|| {
+        format!("no SPI config named {}", config.spi_global_config)
+    })?;
+    let spi_devices = u8::try_from(spi.devices.len())?;
+    for (role, device) in [
+        ("seq_spi_device", config.seq_spi_device),
+        ("ice40_spi_device", config.ice40_spi_device),
+    ] {
+        if device >= spi_devices {
+            return Err(format!(
+                "{} is {}, but {} only has {} devices",
+                role, device, config.spi_global_config, spi_devices
+            )
+            .into());
+        }
+    }
+
+    // All of the enables are driven at once, and all of the PGs are sampled
+    // at once, so each set has to live on a single port.
+    let first = config.rails.first().ok_or("no iCE40 rails configured")?;
+    let enables_port = first.enable.port;
+    let pgs_port = first.pg.port;
+    for rail in &config.rails {
+        if rail.enable.port != enables_port || rail.pg.port != pgs_port {
+            return Err(format!(
+                "rail {} isn't on the same enable and PG ports as {}",
+                rail.name, first.name
+            )
+            .into());
+        }
+    }
+    let enables = config.rails.iter().fold(0, |m, r| m | r.enable.mask());
+    let pgs = config.rails.iter().fold(0, |m, r| m | r.pg.mask());
+
+    let mut output = String::new();
+
+    writeln!(
+        &mut output,
+        r##"
+const SEQ_SPI_DEVICE: u8 = {};
+const ICE40_SPI_DEVICE: u8 = {};
+
+/// How many devices our SPI server has, and so the bound on device indices
+const SPI_DEVICES: u8 = {};
+
+const ICE40_CONFIG: ice40::Config = ice40::Config {{
+    creset: {},
+    cdone: {},
+}};
+"##,
+        config.seq_spi_device,
+        config.ice40_spi_device,
+        spi_devices,
+        config.creset.pinset(),
+        config.cdone.pinset(),
+    )?;
+
+    match config.global_reset {
+        Some(pin) => writeln!(
+            &mut output,
+            "const GLOBAL_RESET: Option<sys_api::PinSet> = Some({});",
+            pin.pinset()
+        )?,
+        None => writeln!(
+            &mut output,
+            "const GLOBAL_RESET: Option<sys_api::PinSet> = None;"
+        )?,
+    }
+
+    if config.fpga_hack_pins.is_empty() {
+        writeln!(
+            &mut output,
+            "const FPGA_HACK_PINS: Option<&[(sys_api::PinSet, bool)]> = None;"
+        )?;
+    } else {
+        writeln!(
+            &mut output,
+            "const FPGA_HACK_PINS: Option<&[(sys_api::PinSet, bool)]> = \
+             Some(&["
+        )?;
+        for h in &config.fpga_hack_pins {
+            writeln!(
+                &mut output,
+                "    (sys_api::Port::{:?}.pin({}), {}),",
+                h.port, h.pin, h.high
+            )?;
+        }
+        writeln!(&mut output, "]);")?;
+    }
+
+    writeln!(
+        &mut output,
+        r##"
+const UART_TX_ENABLE: sys_api::PinSet = {};
+
+const ENABLES: sys_api::PinSet = sys_api::PinSet {{
+    port: sys_api::Port::{:?},
+    pin_mask: {:#06x},
+}};
+
+const PGS_PORT: sys_api::Port = sys_api::Port::{:?};
+const PGS_PINS: sys_api::PinSet = sys_api::PinSet {{
+    port: PGS_PORT,
+    pin_mask: {:#06x},
+}};
+const PGS_PULL: sys_api::Pull = sys_api::Pull::{:?};
+const PG_SAMPLES: u8 = {};
+
+const ICE40_POWER_UP_MS: u64 = {};
+const ICE40_CHUNK_DELAY_MS: u64 = {};
+"##,
+        config.uart_tx_enable.pinset(),
+        enables_port,
+        enables,
+        pgs_port,
+        pgs,
+        config.pg_pull,
+        config.pg_samples,
+        config.power_up_ms,
+        config.chunk_delay_ms,
+    )?;
+
+    writeln!(
+        &mut output,
+        "const N_RAILS: usize = {};\nconst ICE40_RAILS: [Rail; N_RAILS] = [",
+        config.rails.len()
+    )?;
+    for rail in &config.rails {
+        writeln!(
+            &mut output,
+            r##"    Rail {{
+        name: "{}",
+        enable: {},
+        pg_mask: {:#06x},
+        settle_ms: {},
+        critical: {},
+    }},"##,
+            rail.name,
+            rail.enable.pinset(),
+            rail.pg.mask(),
+            rail.settle_ms,
+            rail.critical,
+        )?;
+    }
+    writeln!(&mut output, "];")?;
+
     Ok(output)
 }
 
+/// Length of an uncompressed iCE40 HX4K/HX8K bitstream, which is what the
+/// sequencer FPGA takes.
+const BITSTREAM_LEN: usize = 135100;
+
+/// Sync word that starts the configuration data in every iCE40 bitstream,
+/// after a few bytes of preamble.
+const BITSTREAM_SYNC: [u8; 4] = [0x7e, 0xaa, 0x99, 0x7e];
+
+/// Catches an empty, truncated, or just plain wrong `fpga.bin` here, rather
+/// than as a mysterious programming failure at boot.
+fn check_bitstream(image: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    if image.len() != BITSTREAM_LEN {
+        return Err(format!(
+            "fpga.bin is {} bytes, but an iCE40 HX4K/HX8K bitstream is {}",
+            image.len(),
+            BITSTREAM_LEN
+        )
+        .into());
+    }
+    if !image[..16].windows(4).any(|w| w == BITSTREAM_SYNC) {
+        return Err("fpga.bin does not look like an iCE40 bitstream".into());
+    }
+    Ok(())
+}
+
+/// Magic number for the bitstream header; this must match `BITSTREAM_MAGIC`
+/// in `seq_spi.rs`.
+const BITSTREAM_MAGIC: u32 = 0x1ce4_0b17;
+
+/// Builds the header that goes in front of the compressed bitstream; see
+/// `BitstreamHeader` in `seq_spi.rs` for the layout.
+fn bitstream_header(ident: u16, payload: &[u8]) -> Vec<u8> {
+    let mut header = vec![];
+    header.extend_from_slice(&BITSTREAM_MAGIC.to_le_bytes());
+    header.extend_from_slice(&ident.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    header.extend_from_slice(&crc32(payload).to_le_bytes());
+    header
+}
+
+/// Bitwise CRC-32 (IEEE 802.3), matching the one in `seq_spi.rs`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Pulls the design's ident out of the reset values of the ID0 and ID1
+/// registers in the register map, which is generated alongside the bitstream.
+fn ident() -> Result<u16, Box<dyn std::error::Error>> {
+    let regs = include_str!("gimlet_regs.json");
+    let node: Node = serde_json::from_str(regs)?;
+
+    let children = if let Node::Addrmap { children } = node {
+        children
+    } else {
+        panic!("top-level node is not addrmap");
+    };
+
+    let reset = |name: &str| -> Result<u16, Box<dyn std::error::Error>> {
+        for child in children.iter() {
+            if let Node::Reg {
+                inst_name,
+                children,
+                ..
+            } = child
+            {
+                if inst_name != name {
+                    continue;
+                }
+                if let Some(Node::Field {
+                    reset: Some(reset), ..
+                }) = children.first()
+                {
+                    return Ok(*reset as u16);
+                }
+            }
+        }
+        Err(format!("no reset value for {}", name).into())
+    };
+
+    Ok(reset("ID0")? << 8 | reset("ID1")?)
+}
+
 fn compress(input: &[u8]) -> Vec<u8> {
     let mut output = vec![];
     gnarle::compress(input, |chunk| {
@@ -3,21 +3,48 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Server for managing the Gimlet sequencing process.
+//!
+//! All of our hardware access is by synchronous IPC to the SPI and I2C
+//! servers, so it's worth spelling out why none of it can block us forever:
+//!
+//! - SPI (the sequencer FPGA, and the iCE40's programming port): we're the
+//!   controller and drive the clock, so there's nothing for the far end to
+//!   stall; a transfer always finishes, even if nobody is listening.
+//! - I2C (the clock generator, and the RAA229618s for VDD_VCORE/VDDCR_SOC):
+//!   the I2C server notices a locked bus or controller, resets it, and
+//!   returns `BusLocked` or `ControllerLocked`. Clock configuration turns that
+//!   into `SeqError::ClockConfigFailed`; the regulator calls unwrap, which
+//!   restarts us and re-runs sequencing from a known state.
+//!
+//! So we don't need timeouts of our own, and new bus accesses should stick to
+//! one of those two patterns rather than waiting on hardware themselves.
+//!
+//! There's no standalone or simulated build: sequencing talks to the sys and
+//! SPI servers directly rather than through a trait we could fake, so this
+//! only builds (and only means anything) on the target.
 
 #![no_std]
 #![no_main]
 
 mod seq_spi;
 
+use core::mem::MaybeUninit;
 use ringbuf::*;
 use userlib::*;
 
 use drv_gimlet_hf_api as hf_api;
-use drv_gimlet_seq_api::{PowerState, SeqError};
+use drv_gimlet_seq_api::{
+    BootKind, BringupRecord, FpgaRegister, FpgaUpdateProgress, FpgaVersion,
+    PowerGpioSnapshot, PowerState, SeqError, FPGA_REG_NAME_LEN,
+    MAX_ICE40_RAILS, NO_RAIL,
+};
+use drv_i2c_api::ResponseCode;
 use drv_ice40_spi_program as ice40;
 use drv_spi_api as spi_api;
 use drv_stm32xx_sys_api as sys_api;
-use idol_runtime::RequestError;
+use idol_runtime::{
+    ClientError, Leased, LenLimit, NotificationHandler, RequestError, R, W,
+};
 use seq_spi::{Addr, Reg};
 
 task_slot!(SYS, sys);
@@ -31,14 +58,31 @@ mod payload;
 
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
-    Ice40Rails(bool, bool),
+    Ice40Rails(u16),
+    Ice40SelfLoad(SelfLoad),
+    Boot(BootKind),
+    Bitstream(u16),
     Reprogram(bool),
+    IdentAttempt(u8, bool),
     Programmed,
     Programming,
-    Ice40PowerGoodV1P2(bool),
-    Ice40PowerGoodV3P3(bool),
+    ChunkDelay(u64),
+    FpgaUpdateBegin(u32),
+    FpgaUpdateFailed(u32),
+    FpgaUpdateDone(u32),
+    Ice40PowerGood(&'static str, bool),
+    Ice40PowerGoodWait(&'static str, u64),
+    Ice40PowerGoodLatency(&'static str, u32),
+    Ice40PowerGoodGlitch(&'static str, u8),
+    Ice40RailFault(&'static str, SeqError),
+    Ice40PgFast(&'static str),
+    Ice40PgStuckLow(&'static str),
+    RailEnableOverride(&'static str, bool),
     RailsOff,
+    DiagnosticHold,
+    HoldReleased,
     Ident(u16),
+    SequencerOnline(u8),
     A1Status(u8),
     A2,
     A1Power(u8, u8),
@@ -49,11 +93,61 @@ enum Trace {
     SetState(PowerState, PowerState),
     ClockConfigWrite,
     ClockConfigSuccess,
+    ClockConfigFailed(ResponseCode),
     None,
 }
 
 ringbuf!(Trace, 64, Trace::None);
 
+const TIMER_MASK: u32 = 1 << 0;
+const TIMER_INTERVAL: u64 = 1000;
+
+/// How many times we'll read the sequencer's ident before deciding that it
+/// needs to be reprogrammed.
+const IDENT_ATTEMPTS: u8 = 3;
+
+/// How many times we'll try to put our own bitstream back after a failed FPGA
+/// update, before giving up and reporting it.
+const RESTORE_ATTEMPTS: u8 = 3;
+
+/// How long we'll wait for a rail's power-good before declaring it faulted,
+/// in milliseconds.
+const PG_TIMEOUT: u64 = 100;
+
+/// Interval between power-good polls, in milliseconds. We start out polling
+/// quickly, since most rails come up fast, then double the interval up to
+/// `PG_POLL_MAX` to cut down on wakeups for a slow one. That's about ten
+/// polls in `PG_TIMEOUT`, which build.rs holds `PG_SAMPLES` below; keep the
+/// two in step.
+const PG_POLL_INITIAL: u64 = 2;
+const PG_POLL_MAX: u64 = 16;
+
+/// What the iCE40 was up to when we got to it, as far as CDONE can tell us.
+#[derive(Copy, Clone, PartialEq)]
+enum SelfLoad {
+    /// Its rails were down, so it can't have done anything.
+    Unpowered,
+    /// It had power but hadn't finished configuring itself, so may have been
+    /// partway through loading from Flash.
+    NotDone,
+    /// It had power and a complete configuration, whether from Flash or from
+    /// a previous life of ours.
+    Done,
+}
+
+/// One of the rails that powers the sequencer FPGA. These are brought up in
+/// order by walking the board's `ICE40_RAILS` table.
+struct Rail {
+    name: &'static str,
+    enable: sys_api::PinSet,
+    pg_mask: u16,
+    /// How long to wait after enabling the rail before believing its PG, in
+    /// milliseconds
+    settle_ms: u64,
+    /// If set, a power-good timeout on this rail stops sequencing.
+    critical: bool,
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let spi = spi_api::Spi::from(SPI.get_task_id());
@@ -78,13 +172,7 @@ fn main() -> ! {
     //
     // If it's just our driver that has reset, this will have no effect, and
     // will continue driving the lines at whatever level we left them in.
-    sys.gpio_configure_output(
-        ENABLES,
-        sys_api::OutputType::PushPull,
-        sys_api::Speed::High,
-        sys_api::Pull::None,
-    )
-    .unwrap();
+    configure_output(&sys, ENABLES);
 
     // To talk to the sequencer we need to configure its pins, obvs. Note that
     // the SPI and CS lines are separately managed by the SPI server; the ice40
@@ -92,11 +180,31 @@ fn main() -> ! {
     // generate surprise resets.
     ice40::configure_pins(&sys, &ICE40_CONFIG);
 
-    let pg = sys.gpio_read_input(PGS_PORT).unwrap();
-    let v1p2 = pg & PG_V1P2_MASK != 0;
-    let v3p3 = pg & PG_V3P3_MASK != 0;
+    let rails = ice40_rails_good(&sys);
+    ringbuf_entry!(Trace::Ice40Rails(rails));
+
+    // If the iCE40 already has power (e.g. because we're restarting), it may
+    // have won the race described below and loaded, or started loading, a
+    // design from Flash. We can't stop that after the fact, but holding
+    // CRESETB low (below) throws away whatever it got, so it's harmless as
+    // long as we note it.
+    let self_load = if rails != ALL_RAILS_GOOD {
+        SelfLoad::Unpowered
+    } else if sys.gpio_read(ICE40_CONFIG.cdone).unwrap() != 0 {
+        SelfLoad::Done
+    } else {
+        SelfLoad::NotDone
+    };
+    ringbuf_entry!(Trace::Ice40SelfLoad(self_load));
 
-    ringbuf_entry!(Trace::Ice40Rails(v1p2, v3p3));
+    // Whatever kept the iCE40 powered also kept everything upstream of it
+    // up, so its rails are what tell a warm start from a cold one.
+    let boot = if self_load == SelfLoad::Unpowered {
+        BootKind::Cold
+    } else {
+        BootKind::Warm
+    };
+    ringbuf_entry!(Trace::Boot(boot));
 
     // Force iCE40 CRESETB low before turning power on. This is nice because it
     // prevents the iCE40 from racing us and deciding it should try to load from
@@ -107,61 +215,44 @@ fn main() -> ! {
     // going to be reading back our enable line states to get the real state
     // being seen by the regulators, etc.
 
-    // The V1P2 regulator comes up first. It may already be on from a past life
-    // of ours. Ensuring that it's on by writing the pin is just as cheap as
-    // sensing its current state, and less code than _conditionally_ writing the
-    // pin, so:
-    sys.gpio_set(ENABLE_V1P2).unwrap();
-
-    // We don't actually know how long ago the regulator turned on. Could have
-    // been _just now_ (above) or may have already been on. We'll use the PG pin
-    // to detect when it's stable. But -- the PG pin on the LT3072 is initially
-    // high when you turn the regulator on, and then takes time to drop if
-    // there's a problem. So, to ensure that there has been at least 1ms since
-    // regulator-on, we will delay for 2.
-    hl::sleep_for(2);
-
-    // Now, monitor the PG pin.
-    loop {
-        // active high
-        let pg = sys.gpio_read_input(PGS_PORT).unwrap() & PG_V1P2_MASK != 0;
-        ringbuf_entry!(Trace::Ice40PowerGoodV1P2(pg));
-        if pg {
-            break;
+    // The rails come up in the order given by `ICE40_RAILS`. Each may already
+    // be on from a past life of ours. Ensuring that it's on by writing the pin
+    // is just as cheap as sensing its current state, and less code than
+    // _conditionally_ writing the pin, so:
+    let mut pg_latency = [0u32; N_RAILS];
+    let mut pg_fast = [false; N_RAILS];
+    for (i, rail) in ICE40_RAILS.iter().enumerate() {
+        match ice40_rail_on(&sys, rail) {
+            Ok((ms, fast)) => {
+                pg_latency[i] = ms;
+                pg_fast[i] = fast;
+            }
+            Err(e) => {
+                ringbuf_entry!(Trace::Ice40RailFault(rail.name, e));
+
+                // A rail that isn't critical can stay down without keeping us
+                // from getting far enough to report the problem; anything
+                // else is fatal, and says why in our restart record.
+                if rail.critical {
+                    panic!("critical rail {} failed: {:?}", rail.name, e);
+                }
+            }
         }
-
-        // Do _not_ burn CPU constantly polling, it's rude. We could also set up
-        // pin-change interrupts but we only do this once per power on, so it
-        // seems like a lot of work.
-        hl::sleep_for(2);
     }
 
-    // We believe V1P2 is good. Now, for V3P3! Set it active (high).
-    sys.gpio_set(ENABLE_V3P3).unwrap();
-
-    // Delay to be sure.
-    hl::sleep_for(2);
+    // Give anything downstream of the rails, and the iCE40 itself, time to
+    // come up.
+    hl::sleep_for(ICE40_POWER_UP_MS);
 
-    // Now, monitor the PG pin.
-    loop {
-        // active high
-        let pg = sys.gpio_read_input(PGS_PORT).unwrap() & PG_V3P3_MASK != 0;
-        ringbuf_entry!(Trace::Ice40PowerGoodV3P3(pg));
-        if pg {
-            break;
-        }
+    // Sequencer FPGA power supply sequencing (meta-sequencing?) is complete.
 
-        // Do _not_ burn CPU constantly polling, it's rude.
-        hl::sleep_for(2);
+    // For bench bring-up, we can stop here -- with the FPGA's rails up, but
+    // before we've programmed or even talked to it -- until we're told to go
+    // on.
+    if cfg!(feature = "diagnostic-hold") {
+        diagnostic_hold(&spi, &sys, boot, pg_latency, pg_fast);
     }
 
-    // Now, V2P5 is chained off V3P3 and comes up on its own with no
-    // synchronization. It takes about 500us in practice. We'll delay for 1ms,
-    // plus give the iCE40 a good 10ms to come out of power-down.
-    hl::sleep_for(1 + 10);
-
-    // Sequencer FPGA power supply sequencing (meta-sequencing?) is complete.
-
     // Now, let's find out if we need to program the sequencer.
 
     if let Some(hacks) = FPGA_HACK_PINS {
@@ -175,13 +266,7 @@ fn main() -> ! {
                 sys.gpio_reset(pin).unwrap();
             }
 
-            sys.gpio_configure_output(
-                pin,
-                sys_api::OutputType::PushPull,
-                sys_api::Speed::High,
-                sys_api::Pull::None,
-            )
-            .unwrap();
+            configure_output(&sys, pin);
         }
     }
 
@@ -192,13 +277,7 @@ fn main() -> ! {
         // right now. It's active low, so, set up the pin before exposing the
         // output to ensure we don't glitch.
         sys.gpio_set(pin).unwrap();
-        sys.gpio_configure_output(
-            pin,
-            sys_api::OutputType::PushPull,
-            sys_api::Speed::High,
-            sys_api::Pull::None,
-        )
-        .unwrap();
+        configure_output(&sys, pin);
     }
 
     // If the sequencer is already loaded and operational, the design loaded
@@ -206,41 +285,36 @@ fn main() -> ! {
     // serve up a recognizable ident code.
     let seq = seq_spi::SequencerFpga::new(spi.device(SEQ_SPI_DEVICE));
 
-    let reprogram = !seq.valid_ident();
+    // Our embedded bitstream is wrapped in a header that tells us which ident
+    // to expect from its design. Check that it's intact before trusting it;
+    // there's no sense in programming the FPGA from a corrupt image.
+    let (header, bitstream) =
+        seq_spi::BitstreamHeader::parse(COMPRESSED_BITSTREAM).unwrap();
+    ringbuf_entry!(Trace::Bitstream(header.ident()));
+
+    // The FPGA's SPI interface may not be settled yet if it has only just
+    // come out of power-down, so give it a few tries (with backoff) before
+    // concluding that it needs to be reprogrammed.
+    let mut reprogram = true;
+    for attempt in 0..IDENT_ATTEMPTS {
+        if attempt > 0 {
+            hl::sleep_for(1 << attempt);
+        }
+        let valid = seq.valid_ident(header.ident());
+        ringbuf_entry!(Trace::IdentAttempt(attempt, valid));
+        if valid {
+            reprogram = false;
+            break;
+        }
+    }
     ringbuf_entry!(Trace::Reprogram(reprogram));
 
     // We only want to reset and reprogram the FPGA when absolutely required.
     if reprogram {
-        if let Some(pin) = GLOBAL_RESET {
-            // Assert the design reset signal (not the same as the FPGA
-            // programming logic reset signal). We do this during reprogramming
-            // to avoid weird races that make our brains hurt.
-            sys.gpio_reset(pin).unwrap();
-        }
+        ringbuf_entry!(Trace::ChunkDelay(ICE40_CHUNK_DELAY_MS));
 
         // Reprogramming will continue until morale improves -- to a point.
-        loop {
-            let prog = spi.device(ICE40_SPI_DEVICE);
-            ringbuf_entry!(Trace::Programming);
-            match reprogram_fpga(&prog, &sys, &ICE40_CONFIG) {
-                Ok(()) => {
-                    // yay
-                    break;
-                }
-                Err(_) => {
-                    // Try and put state back to something reasonable.  We
-                    // don't know if we're still locked, so ignore the
-                    // complaint if we're not.
-                    let _ = prog.release();
-                }
-            }
-        }
-
-        if let Some(pin) = GLOBAL_RESET {
-            // Deassert design reset signal. We set the pin, as it's
-            // active low.
-            sys.gpio_set(pin).unwrap();
-        }
+        while !load_embedded_bitstream(&spi, &sys, bitstream) {}
     }
 
     ringbuf_entry!(Trace::Programmed);
@@ -248,16 +322,28 @@ fn main() -> ! {
     vcore_soc_off();
     ringbuf_entry!(Trace::RailsOff);
 
-    let ident = seq.read_ident().unwrap();
+    // If we just programmed it, the design's SPI interface may take a moment
+    // to come up, so give our first read the same backoff as the ident check
+    // above rather than failing on it.
+    let mut attempt = 0;
+    let ident = loop {
+        match seq.read_ident() {
+            Ok(ident) => break ident,
+            Err(_) if attempt + 1 < IDENT_ATTEMPTS => {
+                attempt += 1;
+                hl::sleep_for(1 << attempt);
+            }
+            Err(e) => panic!("sequencer FPGA not responding: {:?}", e),
+        }
+    };
     ringbuf_entry!(Trace::Ident(ident));
+    ringbuf_entry!(Trace::SequencerOnline(attempt));
 
     loop {
-        let mut status = [0u8];
-
-        seq.read_bytes(Addr::PWRCTRL, &mut status).unwrap();
-        ringbuf_entry!(Trace::A1Status(status[0]));
+        let status = seq.read_pwrctrl().unwrap();
+        ringbuf_entry!(Trace::A1Status(status));
 
-        if status[0] == 0 {
+        if status == 0 {
             break;
         }
 
@@ -273,34 +359,227 @@ fn main() -> ! {
     //
     // And now load our clock configuration
     //
-    let clockgen = i2c_config::devices::idt8a34003(I2C.get_task_id())[0];
-
-    payload::idt8a3xxxx_payload(|buf| match clockgen.write(buf) {
-        Err(err) => Err(err),
-        Ok(_) => {
-            ringbuf_entry!(Trace::ClockConfigWrite);
-            Ok(())
-        }
-    })
-    .unwrap();
-
-    ringbuf_entry!(Trace::ClockConfigSuccess);
+    write_clock_config().unwrap();
     ringbuf_entry!(Trace::A2);
 
     let mut buffer = [0; idl::INCOMING_SIZE];
+
+    //
+    // This will put our timer in the past, and should immediately kick us.
+    //
+    let deadline = sys_get_timer().now;
+
+    // A non-critical rail may have been left down above, so start from what
+    // we see now rather than assuming.
+    let ice40_rails = ice40_rails_good(&sys);
+
     let mut server = ServerImpl {
         state: PowerState::A2,
+        held: false,
+        spi,
         seq,
+        bitstream: header,
+        sys,
+        deadline,
+        ice40_rails,
+        boot,
+        pg_latency,
+        pg_fast,
+        update: None,
     };
 
     loop {
-        idol_runtime::dispatch(&mut buffer, &mut server);
+        //
+        // Anything that sleeps (e.g. set_state) clobbers the task timer, so
+        // rearm it each time around before we wait for our next message.
+        //
+        sys_set_timer(Some(server.deadline), TIMER_MASK);
+        idol_runtime::dispatch_n(&mut buffer, &mut server);
     }
 }
 
+/// Serves IPCs with the sequencer FPGA powered but otherwise untouched, until
+/// someone calls `release_hold`. Until then, we claim to be in A2 but refuse
+/// to leave it.
+fn diagnostic_hold(
+    spi: &spi_api::Spi,
+    sys: &sys_api::Sys,
+    boot: BootKind,
+    pg_latency: [u32; N_RAILS],
+    pg_fast: [bool; N_RAILS],
+) {
+    ringbuf_entry!(Trace::DiagnosticHold);
+
+    let (header, _) =
+        seq_spi::BitstreamHeader::parse(COMPRESSED_BITSTREAM).unwrap();
+    let ice40_rails = ice40_rails_good(sys);
+
+    let mut server = ServerImpl {
+        state: PowerState::A2,
+        held: true,
+        spi: spi.clone(),
+        seq: seq_spi::SequencerFpga::new(spi.device(SEQ_SPI_DEVICE)),
+        bitstream: header,
+        sys: sys.clone(),
+        deadline: sys_get_timer().now,
+        ice40_rails,
+        boot,
+        pg_latency,
+        pg_fast,
+        update: None,
+    };
+
+    let mut buffer = [0; idl::INCOMING_SIZE];
+    while server.held {
+        sys_set_timer(Some(server.deadline), TIMER_MASK);
+        idol_runtime::dispatch_n(&mut buffer, &mut server);
+    }
+
+    ringbuf_entry!(Trace::HoldReleased);
+}
+
 struct ServerImpl {
     state: PowerState,
+    /// Set while we're stopped in `diagnostic_hold`
+    held: bool,
+    spi: spi_api::Spi,
     seq: seq_spi::SequencerFpga,
+    /// Header of the bitstream that we carry
+    bitstream: seq_spi::BitstreamHeader,
+    sys: sys_api::Sys,
+    deadline: u64,
+    /// Which of `ICE40_RAILS` had power-good when we last looked, as from
+    /// `ice40_rails_good`
+    ice40_rails: u16,
+    /// Whether the iCE40 was already powered when we started
+    boot: BootKind,
+    /// Time from enable to power-good for each of `ICE40_RAILS`, in ms
+    pg_latency: [u32; N_RAILS],
+    /// Which of `ICE40_RAILS` had power-good suspiciously fast, as from
+    /// `ice40_rail_on`
+    pg_fast: [bool; N_RAILS],
+    /// Set between `begin_fpga_update` and `finish_fpga_update`
+    update: Option<FpgaUpdate>,
+}
+
+/// Marks `LAST_BRINGUP` as holding a real record, rather than whatever was in
+/// RAM when the SP came out of reset.
+const BRINGUP_MAGIC: u32 = 0xb0a7_a0a0;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct RetainedBringup {
+    magic: u32,
+    record: BringupRecord,
+}
+
+/// The last successful bring-up. This lives in `.uninit`, which the runtime
+/// doesn't touch when we start, so it survives our own restarts (but not the
+/// SP's, hence the magic).
+#[link_section = ".uninit"]
+static mut LAST_BRINGUP: MaybeUninit<RetainedBringup> = MaybeUninit::uninit();
+
+fn save_bringup(record: BringupRecord) {
+    let retained = RetainedBringup {
+        magic: BRINGUP_MAGIC,
+        record,
+    };
+    // SAFETY: we're the only thread, and nothing holds a reference to this.
+    unsafe {
+        core::ptr::addr_of_mut!(LAST_BRINGUP)
+            .cast::<RetainedBringup>()
+            .write_volatile(retained)
+    }
+}
+
+fn load_bringup() -> Option<BringupRecord> {
+    // SAFETY: as in `save_bringup`; and every field is plain data, valid for
+    // any bit pattern, so reading garbage is harmless -- we then check the
+    // magic before believing it.
+    let retained = unsafe {
+        core::ptr::addr_of!(LAST_BRINGUP)
+            .cast::<RetainedBringup>()
+            .read_volatile()
+    };
+    if retained.magic == BRINGUP_MAGIC {
+        Some(retained.record)
+    } else {
+        None
+    }
+}
+
+/// Progress of an FPGA update being streamed to us over IPC.
+#[derive(Copy, Clone)]
+struct FpgaUpdate {
+    /// CRC-32 of the bitstream received so far
+    crc: u32,
+    /// Bytes received so far
+    len: u32,
+    /// Bytes that we've been told to expect in all
+    total: u32,
+}
+
+impl ServerImpl {
+    /// Fails if an FPGA update is in progress. The update holds the SPI
+    /// controller from one IPC to the next, and while it does, the SPI server
+    /// refuses everything else we'd send the sequencer.
+    fn check_no_update(&self) -> Result<(), SeqError> {
+        if self.update.is_some() {
+            Err(SeqError::UpdateInProgress)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Drops any FPGA update in progress, along with its hold on the SPI
+    /// controller.
+    fn cancel_fpga_update(&mut self) {
+        if let Some(update) = self.update.take() {
+            ringbuf_entry!(Trace::FpgaUpdateFailed(update.len));
+        }
+
+        // We may or may not still hold the SPI lock from the update.
+        let _ = self.spi.device(ICE40_SPI_DEVICE).release();
+    }
+
+    /// Gives up on an FPGA update, putting back the bitstream that we carry.
+    /// This is an IPC handler's error path, so it only gets a few tries; if
+    /// none of them take, the FPGA is left unprogrammed and we say so.
+    fn abort_fpga_update(&mut self) -> RequestError<SeqError> {
+        self.cancel_fpga_update();
+
+        let (_, bitstream) =
+            seq_spi::BitstreamHeader::parse(COMPRESSED_BITSTREAM).unwrap();
+        for _ in 0..RESTORE_ATTEMPTS {
+            if load_embedded_bitstream(&self.spi, &self.sys, bitstream) {
+                return SeqError::UpdateFailed.into();
+            }
+        }
+
+        SeqError::ReprogramFailed.into()
+    }
+
+    /// Samples the iCE40 power-good pins, logging any change since we last
+    /// looked.
+    fn poll_ice40_rails(&mut self) {
+        let rails = ice40_rails_good(&self.sys);
+
+        if rails != self.ice40_rails {
+            ringbuf_entry!(Trace::Ice40Rails(rails));
+            self.ice40_rails = rails;
+        }
+    }
+}
+
+impl NotificationHandler for ServerImpl {
+    fn current_notification_mask(&self) -> u32 {
+        TIMER_MASK
+    }
+
+    fn handle_notification(&mut self, _bits: u32) {
+        self.deadline += TIMER_INTERVAL;
+        self.poll_ice40_rails();
+    }
 }
 
 impl idl::InOrderSequencerImpl for ServerImpl {
@@ -319,6 +598,12 @@ impl idl::InOrderSequencerImpl for ServerImpl {
     ) -> Result<(), RequestError<SeqError>> {
         ringbuf_entry!(Trace::SetState(self.state, state));
 
+        if self.held {
+            return Err(SeqError::Held.into());
+        }
+
+        self.check_no_update()?;
+
         match (self.state, state) {
             (PowerState::A2, PowerState::A0) => {
                 //
@@ -334,7 +619,7 @@ impl idl::InOrderSequencerImpl for ServerImpl {
                 // We are going to pass through A1 on the way to A0.
                 //
                 let a1a0 = Reg::PWRCTRL::A1PWREN | Reg::PWRCTRL::A0A_EN;
-                self.seq.write_bytes(Addr::PWRCTRL, &[a1a0]).unwrap();
+                self.seq.write_pwrctrl(a1a0).unwrap();
 
                 loop {
                     let mut power = [0u8, 0u8];
@@ -359,12 +644,10 @@ impl idl::InOrderSequencerImpl for ServerImpl {
                 // Now wait for the end of Group C.
                 //
                 loop {
-                    let mut power = [0u8];
-
-                    self.seq.read_bytes(Addr::A0SMSTATUS, &mut power).unwrap();
-                    ringbuf_entry!(Trace::A0Power(power[0]));
+                    let power = self.seq.read_a0smstatus().unwrap();
+                    ringbuf_entry!(Trace::A0Power(power));
 
-                    if power[0] == 0xc {
+                    if power == 0xc {
                         break;
                     }
 
@@ -378,6 +661,16 @@ impl idl::InOrderSequencerImpl for ServerImpl {
                 ringbuf_entry!(Trace::UartEnabled);
 
                 self.state = PowerState::A0;
+
+                save_bringup(BringupRecord {
+                    timestamp: sys_get_timer().now,
+                    pg_latency: pg_latency_reply(&self.pg_latency),
+                    pg_fast: pg_fast_reply(&self.pg_fast),
+                    bitstream_crc: self.bitstream.crc(),
+                    fpga_ident: self.seq.read_ident().unwrap_or(0),
+                    valid: 1,
+                    matches_current: 0,
+                });
                 Ok(())
             }
 
@@ -385,7 +678,7 @@ impl idl::InOrderSequencerImpl for ServerImpl {
                 let hf = hf_api::HostFlash::from(HF.get_task_id());
                 let a1a0 = Reg::PWRCTRL::A0C_DIS;
 
-                self.seq.write_bytes(Addr::PWRCTRL, &[a1a0]).unwrap();
+                self.seq.write_pwrctrl(a1a0).unwrap();
                 vcore_soc_off();
 
                 if let Err(_) = hf.set_mux(hf_api::HfMuxState::SP) {
@@ -401,10 +694,26 @@ impl idl::InOrderSequencerImpl for ServerImpl {
         }
     }
 
+    fn ensure_state(
+        &mut self,
+        msg: &RecvMessage,
+        state: PowerState,
+    ) -> Result<PowerState, RequestError<SeqError>> {
+        //
+        // Unlike `set_state`, being in the target state already isn't an
+        // error; anything else goes through the usual transition.
+        //
+        if self.state != state {
+            idl::InOrderSequencerImpl::set_state(self, msg, state)?;
+        }
+        Ok(self.state)
+    }
+
     fn fans_on(
         &mut self,
         _: &RecvMessage,
     ) -> Result<(), RequestError<SeqError>> {
+        self.check_no_update()?;
         let on = Reg::EARLY_POWER_CTRL::FANPWREN;
         self.seq.set_bytes(Addr::EARLY_POWER_CTRL, &[on]).unwrap();
         Ok(())
@@ -414,6 +723,7 @@ impl idl::InOrderSequencerImpl for ServerImpl {
         &mut self,
         _: &RecvMessage,
     ) -> Result<(), RequestError<SeqError>> {
+        self.check_no_update()?;
         let off = Reg::EARLY_POWER_CTRL::FANPWREN;
         self.seq
             .clear_bytes(Addr::EARLY_POWER_CTRL, &[off])
@@ -421,6 +731,349 @@ impl idl::InOrderSequencerImpl for ServerImpl {
         Ok(())
     }
 
+    //
+    // This covers A1SMSTATUS through CLKGEN_OUT_STATUS: the state machine
+    // states, followed by the power-good readbacks and output enables. The
+    // bit layout of each register is in `Reg`.
+    //
+    fn read_a1_sm_status(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<[u8; 20], RequestError<SeqError>> {
+        self.check_no_update()?;
+        let mut status = [0u8; 20];
+
+        // A single read tops out at seq_spi::MAX_DATA_SIZE, well short of 20
+        // bytes, so split it up.
+        let (lo, hi) = status.split_at_mut(10);
+        self.seq.read_bytes(Addr::A1SMSTATUS, lo).unwrap();
+        self.seq
+            .read_bytes(u16::from(Addr::A1SMSTATUS) + 10, hi)
+            .unwrap();
+
+        Ok(status)
+    }
+
+    //
+    // For bring-up of a board whose sequencer hangs off a different chip
+    // select than the board config says: point us at another SPI device.
+    // The iCE40 programming port is only used at boot, so it can't be
+    // remapped this way.
+    //
+    fn set_seq_spi_device(
+        &mut self,
+        _: &RecvMessage,
+        device: u8,
+    ) -> Result<(), RequestError<SeqError>> {
+        self.check_no_update()?;
+
+        // We can't tell an empty chip select from a sequencer that isn't
+        // answering yet, so all we check is that there's such a device.
+        if device >= SPI_DEVICES {
+            return Err(SeqError::BadSpiDevice.into());
+        }
+
+        self.seq = seq_spi::SequencerFpga::new(self.spi.device(device));
+        Ok(())
+    }
+
+    //
+    // Bench-only: drives the enable of one of `ICE40_RAILS` directly, with no
+    // regard for sequencing. Dropping a rail out from under a running FPGA,
+    // or bringing one up ahead of those before it in the table, can leave the
+    // board in a state that only a power cycle will fix -- so this does
+    // nothing unless we're built with the `rail-debug` feature.
+    //
+    fn set_rail_enable(
+        &mut self,
+        _: &RecvMessage,
+        rail: u8,
+        on: u8,
+    ) -> Result<(), RequestError<SeqError>> {
+        if !cfg!(feature = "rail-debug") {
+            return Err(SeqError::NotSupported.into());
+        }
+
+        let rail = ICE40_RAILS
+            .get(usize::from(rail))
+            .ok_or(SeqError::BadRail)?;
+
+        ringbuf_entry!(Trace::RailEnableOverride(rail.name, on != 0));
+        self.sys.gpio_set_to(rail.enable, on != 0).unwrap();
+        Ok(())
+    }
+
+    fn get_fpga_version(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<FpgaVersion, RequestError<SeqError>> {
+        self.check_no_update()?;
+        Ok(FpgaVersion {
+            ident: self.bitstream.ident(),
+            fpga_ident: self.seq.read_ident().unwrap(),
+            crc: self.bitstream.crc(),
+        })
+    }
+
+    fn get_last_bringup(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<BringupRecord, RequestError<SeqError>> {
+        let mut record = match load_bringup() {
+            Some(record) => record,
+            None => return Ok(BringupRecord::default()),
+        };
+
+        let matches = self.state == PowerState::A0
+            && record.bitstream_crc == self.bitstream.crc()
+            && self.seq.read_ident().ok() == Some(record.fpga_ident);
+        record.matches_current = matches as u8;
+
+        Ok(record)
+    }
+
+    fn release_hold(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<SeqError>> {
+        self.held = false;
+        Ok(())
+    }
+
+    //
+    // Reprograms the sequencer FPGA with an uncompressed bitstream, streamed
+    // to us a chunk at a time. The sequencer runs the power state machine, so
+    // this is only allowed in A2, and we won't leave A2 until it's done; nor
+    // will we talk to the sequencer, since the update holds the SPI
+    // controller throughout. A diagnostic hold is for looking at the FPGA as
+    // we found it, so that rules out an update too.
+    // Calling this again mid-update abandons the old update and starts over,
+    // so a client that died partway through can't wedge us. If anything goes
+    // wrong, we try to put back the bitstream that we carry, failing with
+    // `ReprogramFailed` if even that won't take.
+    //
+    // The new design only lasts until we restart: its ident won't match the
+    // one in our bitstream's header, so we'll reprogram it then.
+    //
+    // We're told the bitstream's length up front, so that progress can be
+    // reported as a fraction of it, and so that we can refuse one that runs
+    // long or comes up short.
+    //
+    fn begin_fpga_update(
+        &mut self,
+        _: &RecvMessage,
+        len: u32,
+    ) -> Result<(), RequestError<SeqError>> {
+        if self.held {
+            return Err(SeqError::Held.into());
+        }
+
+        if self.state != PowerState::A2 {
+            return Err(SeqError::IllegalTransition.into());
+        }
+
+        self.cancel_fpga_update();
+
+        ringbuf_entry!(Trace::FpgaUpdateBegin(len));
+        self.update = Some(FpgaUpdate {
+            crc: 0,
+            len: 0,
+            total: len,
+        });
+
+        if let Some(pin) = GLOBAL_RESET {
+            self.sys.gpio_reset(pin).unwrap();
+        }
+
+        let prog = self.spi.device(ICE40_SPI_DEVICE);
+        if ice40::begin_bitstream_load(&prog, &self.sys, &ICE40_CONFIG).is_err()
+        {
+            return Err(self.abort_fpga_update());
+        }
+
+        Ok(())
+    }
+
+    fn fpga_update_chunk(
+        &mut self,
+        _: &RecvMessage,
+        data: LenLimit<Leased<R, [u8]>, 256>,
+    ) -> Result<(), RequestError<SeqError>> {
+        let update = self.update.ok_or(SeqError::NoUpdateInProgress)?;
+
+        let mut buf = [0u8; 256];
+        let chunk = &mut buf[..data.len()];
+        data.read_range(0..data.len(), chunk)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        if update.len + chunk.len() as u32 > update.total {
+            return Err(self.abort_fpga_update());
+        }
+
+        let prog = self.spi.device(ICE40_SPI_DEVICE);
+        if ice40::continue_bitstream_load(&prog, chunk).is_err() {
+            return Err(self.abort_fpga_update());
+        }
+        if ICE40_CHUNK_DELAY_MS != 0 {
+            hl::sleep_for(ICE40_CHUNK_DELAY_MS);
+        }
+
+        self.update = Some(FpgaUpdate {
+            crc: seq_spi::crc32(update.crc, chunk),
+            len: update.len + chunk.len() as u32,
+            ..update
+        });
+        Ok(())
+    }
+
+    fn finish_fpga_update(
+        &mut self,
+        _: &RecvMessage,
+        crc: u32,
+    ) -> Result<(), RequestError<SeqError>> {
+        let update = self.update.ok_or(SeqError::NoUpdateInProgress)?;
+
+        // The FPGA has no idea whether what we sent it was intact, so that's
+        // on us; don't run a design that didn't arrive as it was sent.
+        if update.len != update.total || update.crc != crc {
+            return Err(self.abort_fpga_update());
+        }
+
+        let prog = self.spi.device(ICE40_SPI_DEVICE);
+        if ice40::finish_bitstream_load(&prog, &self.sys, &ICE40_CONFIG)
+            .is_err()
+        {
+            return Err(self.abort_fpga_update());
+        }
+
+        if let Some(pin) = GLOBAL_RESET {
+            self.sys.gpio_set(pin).unwrap();
+        }
+
+        // Make sure the new design will at least talk to us; all zeros or
+        // all ones means that nothing is driving the bus.
+        match self.seq.read_ident() {
+            Ok(ident) if ident != 0 && ident != 0xffff => {}
+            _ => return Err(self.abort_fpga_update()),
+        }
+
+        self.update = None;
+        ringbuf_entry!(Trace::FpgaUpdateDone(update.len));
+        Ok(())
+    }
+
+    //
+    // Unlike `ice40_rails`, this isn't filtered through the PG masks: it's
+    // the raw port state, for lining up with a scope capture when a rail
+    // isn't doing what its PG says.
+    //
+    fn get_power_gpio_snapshot(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<PowerGpioSnapshot, RequestError<SeqError>> {
+        let sys = &self.sys;
+        let timestamp = sys_get_timer().now;
+
+        Ok(PowerGpioSnapshot {
+            timestamp,
+            pgs: sys.gpio_read_input(PGS_PORT).unwrap(),
+            enables: sys.gpio_read_input(ENABLES.port).unwrap(),
+            cdone: sys.gpio_read(ICE40_CONFIG.cdone).unwrap(),
+            creset: sys.gpio_read(ICE40_CONFIG.creset).unwrap(),
+        })
+    }
+
+    fn get_boot_kind(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<BootKind, RequestError<SeqError>> {
+        Ok(self.boot)
+    }
+
+    fn get_ice40_pg_latency(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<[u32; MAX_ICE40_RAILS], RequestError<SeqError>> {
+        Ok(pg_latency_reply(&self.pg_latency))
+    }
+
+    //
+    // Reads as many registers as `data` has room for, starting at `addr`.
+    // A read that runs past the end of the register map is refused, rather
+    // than quietly coming back short.
+    //
+    fn read_fpga_regs(
+        &mut self,
+        _: &RecvMessage,
+        addr: u16,
+        data: LenLimit<Leased<W, [u8]>, 256>,
+    ) -> Result<(), RequestError<SeqError>> {
+        self.check_no_update()?;
+        if usize::from(addr) + data.len() > usize::from(seq_spi::ADDR_END) {
+            return Err(SeqError::BadRegister.into());
+        }
+
+        let mut buf = [0u8; seq_spi::MAX_DATA_SIZE];
+        let mut offset = 0;
+        while offset < data.len() {
+            let n = (data.len() - offset).min(buf.len());
+            self.seq
+                .read_bytes(addr + offset as u16, &mut buf[..n])
+                .unwrap();
+            data.write_range(offset..offset + n, &buf[..n])
+                .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+            offset += n;
+        }
+
+        Ok(())
+    }
+
+    //
+    // Lets a debugger walk the register map by index (until we say
+    // `BadRegister`), so that it can label what `read_fpga_regs` returns.
+    //
+    fn get_fpga_reg(
+        &mut self,
+        _: &RecvMessage,
+        index: u16,
+    ) -> Result<FpgaRegister, RequestError<SeqError>> {
+        let &(addr, name, width) = seq_spi::REGISTERS
+            .get(usize::from(index))
+            .ok_or(SeqError::BadRegister)?;
+
+        let mut reg = FpgaRegister {
+            addr: addr.into(),
+            width,
+            name_len: name.len() as u8,
+            name: [0; FPGA_REG_NAME_LEN],
+        };
+        reg.name[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(reg)
+    }
+
+    fn get_fpga_update_progress(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<FpgaUpdateProgress, RequestError<SeqError>> {
+        let update = self.update.ok_or(SeqError::NoUpdateInProgress)?;
+        Ok(FpgaUpdateProgress {
+            received: update.len,
+            total: update.total,
+        })
+    }
+
+    //
+    // We load the clock config at boot, but allow it to be reloaded, e.g. if
+    // the clock generator has been reset out from under us.
+    //
+    fn load_clock_config(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<SeqError>> {
+        write_clock_config()?;
+        Ok(())
+    }
+
     //
     // By the time we are hanging out the shingle, the clock config is loaded.
     //
@@ -432,23 +1085,217 @@ impl idl::InOrderSequencerImpl for ServerImpl {
     }
 }
 
+/// Streams the configuration payload to our clock generator.
+fn write_clock_config() -> Result<(), SeqError> {
+    let clockgen = i2c_config::devices::idt8a34003(I2C.get_task_id())[0];
+
+    payload::idt8a3xxxx_payload(|buf| match clockgen.write(buf) {
+        Err(err) => {
+            ringbuf_entry!(Trace::ClockConfigFailed(err));
+            Err(SeqError::ClockConfigFailed)
+        }
+        Ok(_) => {
+            ringbuf_entry!(Trace::ClockConfigWrite);
+            Ok(())
+        }
+    })?;
+
+    ringbuf_entry!(Trace::ClockConfigSuccess);
+    Ok(())
+}
+
+/// Configures `pins` as outputs the way all of ours are: push-pull, high
+/// speed, no pull. Set the level first if it matters, so the pins don't glitch.
+fn configure_output(sys: &sys_api::Sys, pins: sys_api::PinSet) {
+    sys.gpio_configure_output(
+        pins,
+        sys_api::OutputType::PushPull,
+        sys_api::Speed::High,
+        sys_api::Pull::None,
+    )
+    .unwrap();
+}
+
+/// Reads all of our power-good pins with a single GPIO read, so that every
+/// rail is sampled at the same instant. Bits outside of `PGS_PINS` are masked
+/// off.
+fn read_all_pg(sys: &sys_api::Sys) -> u16 {
+    sys.gpio_read_input(PGS_PORT).unwrap() & PGS_PINS.pin_mask
+}
+
+/// Returns a bitmask with bit `i` set if `ICE40_RAILS[i]` has power-good.
+fn ice40_rails_good(sys: &sys_api::Sys) -> u16 {
+    let pg = read_all_pg(sys);
+    ICE40_RAILS
+        .iter()
+        .enumerate()
+        .filter(|(_, rail)| pg & rail.pg_mask != 0)
+        .fold(0, |good, (i, _)| good | 1 << i)
+}
+
+/// What `ice40_rails_good` returns once every rail is up.
+const ALL_RAILS_GOOD: u16 = u16::MAX >> (16 - N_RAILS);
+
+/// Pads per-rail latencies out to the fixed size that goes over IPC.
+fn pg_latency_reply(latency: &[u32; N_RAILS]) -> [u32; MAX_ICE40_RAILS] {
+    let mut reply = [NO_RAIL; MAX_ICE40_RAILS];
+    reply[..N_RAILS].copy_from_slice(latency);
+    reply
+}
+
+/// Pads per-rail fast-PG flags out to the fixed size that goes over IPC.
+fn pg_fast_reply(fast: &[bool; N_RAILS]) -> [u8; MAX_ICE40_RAILS] {
+    let mut reply = [0; MAX_ICE40_RAILS];
+    for (r, &f) in reply.iter_mut().zip(fast) {
+        *r = f as u8;
+    }
+    reply
+}
+
+/// Turns on one of `ICE40_RAILS` and waits for its power-good, returning the
+/// time that took in milliseconds, and whether the PG was suspiciously fast.
+///
+/// A rail that we just turned on shouldn't have good power by the time we
+/// first look, so if its PG is already asserted then, the pin may be stuck
+/// (or shorted) high. Some regulators (e.g. the LT3072) really do assert PG
+/// as they turn on, though, so this is only noted, for the bring-up record,
+/// rather than treated as a failure.
+fn ice40_rail_on(
+    sys: &sys_api::Sys,
+    rail: &Rail,
+) -> Result<(u32, bool), SeqError> {
+    let was_on = sys.gpio_read(rail.enable).unwrap() != 0;
+
+    sys.gpio_set(rail.enable).unwrap();
+    let enabled_at = sys_get_timer().now;
+
+    // We don't actually know how long ago the regulator turned on. Could
+    // have been _just now_ (above) or may have already been on. We'll use
+    // the PG pin to detect when it's stable, once it has had time to
+    // settle.
+    hl::sleep_for(rail.settle_ms);
+
+    let result = wait_for_pg(sys, rail);
+
+    // This is from our enable, so it's only meaningful for a rail which
+    // wasn't already on -- as is a fast PG.
+    let latency = (sys_get_timer().now - enabled_at) as u32;
+    ringbuf_entry!(Trace::Ice40PowerGoodLatency(rail.name, latency));
+
+    let first_pg = result?;
+    let fast = !was_on && first_pg;
+    if fast {
+        ringbuf_entry!(Trace::Ice40PgFast(rail.name));
+    }
+
+    Ok((latency, fast))
+}
+
+/// Polls a rail's power-good pin until it has read as asserted for
+/// `PG_SAMPLES` polls in a row, giving up after `PG_TIMEOUT`. On success,
+/// returns whether the PG was already asserted at the first poll.
+///
+/// A PG that never asserts at all in that time is reported as `PgStuck`,
+/// since the pin may be stuck (or shorted) low; one that comes and goes
+/// without settling is a `PgTimeout`.
+fn wait_for_pg(sys: &sys_api::Sys, rail: &Rail) -> Result<bool, SeqError> {
+    let start = sys_get_timer().now;
+    let mut interval = PG_POLL_INITIAL;
+    let mut samples = 0;
+    let mut seen = false;
+    let mut first_pg = None;
+    loop {
+        // active high
+        let pg = read_all_pg(sys) & rail.pg_mask != 0;
+        ringbuf_entry!(Trace::Ice40PowerGood(rail.name, pg));
+        if first_pg.is_none() {
+            first_pg = Some(pg);
+        }
+
+        if pg {
+            samples += 1;
+            seen = true;
+        } else if samples > 0 {
+            // PG went away again before we believed it; note how far it got.
+            ringbuf_entry!(Trace::Ice40PowerGoodGlitch(rail.name, samples));
+            samples = 0;
+        }
+
+        let waited = sys_get_timer().now - start;
+        if samples >= PG_SAMPLES {
+            ringbuf_entry!(Trace::Ice40PowerGoodWait(rail.name, waited));
+            return Ok(first_pg == Some(true));
+        }
+        if waited >= PG_TIMEOUT {
+            ringbuf_entry!(Trace::Ice40PowerGoodWait(rail.name, waited));
+            if !seen {
+                ringbuf_entry!(Trace::Ice40PgStuckLow(rail.name));
+                return Err(SeqError::PgStuck);
+            }
+            return Err(SeqError::PgTimeout);
+        }
+
+        // Do _not_ burn CPU constantly polling, it's rude. We could also set
+        // up pin-change interrupts but we only do this once per power on, so
+        // it seems like a lot of work.
+        hl::sleep_for(interval.min(PG_TIMEOUT - waited));
+        interval = (interval * 2).min(PG_POLL_MAX);
+    }
+}
+
+/// Makes one attempt at programming the FPGA with the (compressed) bitstream
+/// that we carry, returning `true` if it took. On failure, the design is left
+/// in reset.
+fn load_embedded_bitstream(
+    spi: &spi_api::Spi,
+    sys: &sys_api::Sys,
+    bitstream: &[u8],
+) -> bool {
+    if let Some(pin) = GLOBAL_RESET {
+        // Assert the design reset signal (not the same as the FPGA
+        // programming logic reset signal). We do this during reprogramming
+        // to avoid weird races that make our brains hurt.
+        sys.gpio_reset(pin).unwrap();
+    }
+
+    let prog = spi.device(ICE40_SPI_DEVICE);
+    ringbuf_entry!(Trace::Programming);
+    if reprogram_fpga(&prog, sys, &ICE40_CONFIG, bitstream).is_err() {
+        // Try and put state back to something reasonable.  We don't know if
+        // we're still locked, so ignore the complaint if we're not.
+        let _ = prog.release();
+        return false;
+    }
+
+    if let Some(pin) = GLOBAL_RESET {
+        // Deassert design reset signal. We set the pin, as it's
+        // active low.
+        sys.gpio_set(pin).unwrap();
+    }
+
+    true
+}
+
 fn reprogram_fpga(
     spi: &spi_api::SpiDevice,
     sys: &sys_api::Sys,
     config: &ice40::Config,
+    mut bitstream: &[u8],
 ) -> Result<(), ice40::Ice40Error> {
     ice40::begin_bitstream_load(&spi, &sys, &config)?;
 
     // We've got the bitstream in Flash, so we can technically just send it in
     // one transaction, but we'll want chunking later -- so let's make sure
     // chunking works.
-    let mut bitstream = COMPRESSED_BITSTREAM;
     let mut decompressor = gnarle::Decompressor::default();
     let mut chunk = [0; 256];
     while !bitstream.is_empty() || !decompressor.is_idle() {
         let out =
             gnarle::decompress(&mut decompressor, &mut bitstream, &mut chunk);
         ice40::continue_bitstream_load(&spi, out)?;
+        if ICE40_CHUNK_DELAY_MS != 0 {
+            hl::sleep_for(ICE40_CHUNK_DELAY_MS);
+        }
     }
 
     ice40::finish_bitstream_load(&spi, &sys, &config)
@@ -457,122 +1304,69 @@ fn reprogram_fpga(
 static COMPRESSED_BITSTREAM: &[u8] =
     include_bytes!(concat!(env!("OUT_DIR"), "/fpga.bin.rle"));
 
-cfg_if::cfg_if! {
-    if #[cfg(any(target_board = "gimlet-a", target_board = "gimlet-b"))] {
-        const SEQ_SPI_DEVICE: u8 = 0;
-        const ICE40_SPI_DEVICE: u8 = 1;
-
-        const ICE40_CONFIG: ice40::Config = ice40::Config {
-            // CRESET net is SEQ_TO_SP_CRESET_L and hits PD5.
-            creset: sys_api::Port::D.pin(5),
-
-            // CDONE net is SEQ_TO_SP_CDONE_L and hits PB4.
-            cdone: sys_api::Port::B.pin(4),
-        };
-
-        const GLOBAL_RESET: Option<sys_api::PinSet> = Some(
-            sys_api::Port::A.pin(6)
-        );
-
-        // gimlet-a needs to have a pin flipped to mux the iCE40 SPI flash out
-        // of circuit to be able to program the FPGA, because we accidentally
-        // share a CS net between Flash and the iCE40.
-        //
-        // (port, mask, high_flag)
-        #[cfg(target_board = "gimlet-a")]
-        const FPGA_HACK_PINS: Option<&[(sys_api::PinSet, bool)]> = Some(&[
-            // SEQ_TO_SEQ_MUX_SEL, pulled high, we drive it low
-            (sys_api::Port::I.pin(8), false),
-        ]);
-
-        #[cfg(target_board = "gimlet-b")]
-        const FPGA_HACK_PINS: Option<&[(sys_api::PinSet, bool)]> = None;
-
-        //
-        // SP_TO_SP3_UARTA_OE_L must be driven low to allow for transmission
-        // into the SP3's UART
-        //
-        const UART_TX_ENABLE: sys_api::PinSet = sys_api::Port::A.pin(5);
+// Device indices, pins, and rail timing all come from the task's config
+// section in the app.toml; adding a board shouldn't need any code here.
+include!(concat!(env!("OUT_DIR"), "/seq_config.rs"));
 
-        fn uart_sp_to_sp3_enable() {
-            let sys = sys_api::Sys::from(SYS.get_task_id());
-
-            sys.gpio_configure_output(
-                UART_TX_ENABLE,
-                sys_api::OutputType::PushPull,
-                sys_api::Speed::High,
-                sys_api::Pull::None,
-            )
-            .unwrap();
-
-            sys.gpio_reset(UART_TX_ENABLE).unwrap();
-        }
-
-        const ENABLES_PORT: sys_api::Port = sys_api::Port::A;
-        const ENABLE_V1P2_MASK: u16 = 1 << 15;
-        const ENABLE_V3P3_MASK: u16 = 1 << 4;
-
-        const ENABLES: sys_api::PinSet = sys_api::PinSet {
-            port: ENABLES_PORT,
-            pin_mask: ENABLE_V1P2_MASK | ENABLE_V3P3_MASK,
-        };
-
-        const ENABLE_V1P2: sys_api::PinSet = sys_api::PinSet {
-            port: ENABLES_PORT,
-            pin_mask: ENABLE_V1P2_MASK,
-        };
-
-        const ENABLE_V3P3: sys_api::PinSet = sys_api::PinSet {
-            port: ENABLES_PORT,
-            pin_mask: ENABLE_V3P3_MASK,
-        };
+fn uart_sp_to_sp3_enable() {
+    let sys = sys_api::Sys::from(SYS.get_task_id());
 
-        const PGS_PORT: sys_api::Port = sys_api::Port::C;
-        const PG_V1P2_MASK: u16 = 1 << 7;
-        const PG_V3P3_MASK: u16 = 1 << 6;
+    configure_output(&sys, UART_TX_ENABLE);
 
-        const PGS_PINS: sys_api::PinSet = sys_api::PinSet {
-            port: PGS_PORT,
-            pin_mask: PG_V1P2_MASK | PG_V3P3_MASK
-        };
+    sys.gpio_reset(UART_TX_ENABLE).unwrap();
+}
 
-        // Gimlet provides external pullups.
-        const PGS_PULL: sys_api::Pull = sys_api::Pull::None;
+fn vcore_soc_off() {
+    use drv_i2c_devices::raa229618::Raa229618;
+    let i2c = I2C.get_task_id();
 
-        fn vcore_soc_off() {
-            use drv_i2c_devices::raa229618::Raa229618;
-            let i2c = I2C.get_task_id();
+    let (device, rail) = i2c_config::pmbus::vdd_vcore(i2c);
+    let mut vdd_vcore = Raa229618::new(&device, rail);
 
-            let (device, rail) = i2c_config::pmbus::vdd_vcore(i2c);
-            let mut vdd_vcore = Raa229618::new(&device, rail);
+    let (device, rail) = i2c_config::pmbus::vddcr_soc(i2c);
+    let mut vddcr_soc = Raa229618::new(&device, rail);
 
-            let (device, rail) = i2c_config::pmbus::vddcr_soc(i2c);
-            let mut vddcr_soc = Raa229618::new(&device, rail);
+    vdd_vcore.turn_off().unwrap();
+    vddcr_soc.turn_off().unwrap();
+}
 
-            vdd_vcore.turn_off().unwrap();
-            vddcr_soc.turn_off().unwrap();
-        }
+fn vcore_soc_on() {
+    use drv_i2c_devices::raa229618::Raa229618;
+    let i2c = I2C.get_task_id();
 
-        fn vcore_soc_on() {
-            use drv_i2c_devices::raa229618::Raa229618;
-            let i2c = I2C.get_task_id();
+    let (device, rail) = i2c_config::pmbus::vdd_vcore(i2c);
+    let mut vdd_vcore = Raa229618::new(&device, rail);
 
-            let (device, rail) = i2c_config::pmbus::vdd_vcore(i2c);
-            let mut vdd_vcore = Raa229618::new(&device, rail);
+    let (device, rail) = i2c_config::pmbus::vddcr_soc(i2c);
+    let mut vddcr_soc = Raa229618::new(&device, rail);
 
-            let (device, rail) = i2c_config::pmbus::vddcr_soc(i2c);
-            let mut vddcr_soc = Raa229618::new(&device, rail);
+    vdd_vcore.turn_on().unwrap();
+    vddcr_soc.turn_on().unwrap();
+}
 
-            vdd_vcore.turn_on().unwrap();
-            vddcr_soc.turn_on().unwrap();
-        }
-    } else {
-        compiler_error!("unsupported target board");
+// Every register name has to fit in a `FpgaRegister`.
+const _: () = {
+    let mut i = 0;
+    while i < seq_spi::REGISTERS.len() {
+        assert!(
+            seq_spi::REGISTERS[i].1.len() <= FPGA_REG_NAME_LEN,
+            "FPGA register name too long for FpgaRegister"
+        );
+        i += 1;
     }
-}
+};
+
+// Replies with an entry per rail are sized for the most that any board has.
+const _: () = assert!(
+    N_RAILS <= MAX_ICE40_RAILS,
+    "board config lists more iCE40 rails than the sequencer API allows"
+);
 
 mod idl {
-    use super::{PowerState, SeqError};
+    use super::{
+        BootKind, BringupRecord, FpgaRegister, FpgaUpdateProgress, FpgaVersion,
+        PowerGpioSnapshot, PowerState, SeqError,
+    };
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -6,7 +6,7 @@
 //!
 //! This uses external shared SPI and GPIO servers to drive the FPGA.
 
-use zerocopy::{AsBytes, Unaligned, U16};
+use zerocopy::{AsBytes, FromBytes, LayoutVerified, Unaligned, U16, U32};
 
 use drv_spi_api as spi_api;
 
@@ -21,7 +21,95 @@ pub enum Cmd {
 
 include!(concat!(env!("OUT_DIR"), "/gimlet_regs.rs"));
 
-pub const EXPECTED_IDENT: u16 = 0x1DE;
+/// Magic number at the start of the header that our build script prepends to
+/// the embedded bitstream.  This must match `build.rs`.
+pub const BITSTREAM_MAGIC: u32 = 0x1ce4_0b17;
+
+/// Header prepended to the compressed bitstream at build time, describing the
+/// payload that follows it.
+#[derive(Copy, Clone, AsBytes, FromBytes, Unaligned)]
+#[repr(C)]
+pub struct BitstreamHeader {
+    magic: U32<byteorder::LittleEndian>,
+    /// Ident that the design reports in ID0:1 once it's loaded.
+    ident: U16<byteorder::LittleEndian>,
+    _reserved: U16<byteorder::LittleEndian>,
+    /// Length of the payload following the header, in bytes.
+    len: U32<byteorder::LittleEndian>,
+    /// CRC-32 of the payload following the header.
+    crc: U32<byteorder::LittleEndian>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BitstreamError {
+    /// Too short to even contain a header
+    Truncated,
+    BadMagic(u32),
+    /// The header and payload disagree about the payload length
+    BadLength(u32),
+    /// The payload doesn't match the CRC in the header (computed CRC
+    /// attached)
+    BadCrc(u32),
+}
+
+impl BitstreamHeader {
+    /// Splits `data` into its header and the payload following it, checking
+    /// the header's magic number, length, and CRC against the payload.
+    pub fn parse(data: &[u8]) -> Result<(Self, &[u8]), BitstreamError> {
+        let (header, payload) =
+            LayoutVerified::<_, Self>::new_unaligned_from_prefix(data)
+                .ok_or(BitstreamError::Truncated)?;
+        let header = *header;
+
+        if header.magic.get() != BITSTREAM_MAGIC {
+            return Err(BitstreamError::BadMagic(header.magic.get()));
+        }
+
+        if header.len.get() as usize != payload.len() {
+            return Err(BitstreamError::BadLength(header.len.get()));
+        }
+
+        let crc = crc32(0, payload);
+        if crc != header.crc.get() {
+            return Err(BitstreamError::BadCrc(crc));
+        }
+
+        Ok((header, payload))
+    }
+
+    pub fn ident(&self) -> u16 {
+        self.ident.get()
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc.get()
+    }
+}
+
+/// Bitwise CRC-32 (IEEE 802.3), matching the one in `build.rs`.  This is slow
+/// but small, and we only run it once per boot (or per FPGA update).
+///
+/// `crc` is the CRC of any data that came before `data`, so that a stream can
+/// be checked a chunk at a time; start with 0.
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Size of the buffers we exchange with the FPGA, including the command header.
+const TRANSFER_SIZE: usize = 16;
+
+/// The most data that a single command can read or write; anything longer
+/// is rejected with `SpiError::BadTransferSize` rather than truncated.
+pub const MAX_DATA_SIZE: usize =
+    TRANSFER_SIZE - core::mem::size_of::<CmdHeader>();
 
 pub struct SequencerFpga {
     spi: spi_api::SpiDevice,
@@ -39,17 +127,18 @@ impl SequencerFpga {
         Ok(ident)
     }
 
-    /// Check for a valid identifier, deliberately eating any SPI errors.
-    pub fn valid_ident(&self) -> bool {
+    /// Check for the `expected` identifier, deliberately eating any SPI
+    /// errors.
+    pub fn valid_ident(&self, expected: u16) -> bool {
         if let Ok(ident) = self.read_ident() {
-            ident == EXPECTED_IDENT
+            ident == expected
         } else {
             false
         }
     }
 
-    /// Performs the READ command against `addr`. This can read as many bytes as
-    /// you like into `data_out`.
+    /// Performs the READ command against `addr`, reading up to
+    /// `MAX_DATA_SIZE` bytes into `data_out`.
     pub fn read_bytes(
         &self,
         addr: impl Into<u16>,
@@ -58,8 +147,8 @@ impl SequencerFpga {
         self.raw_spi_read(Cmd::Read, addr.into(), data_out)
     }
 
-    /// Performs the WRITE command against `addr`. This can write as many bytes
-    /// as you like from `data_in`.
+    /// Performs the WRITE command against `addr`, writing up to
+    /// `MAX_DATA_SIZE` bytes from `data_in`.
     pub fn write_bytes(
         &self,
         addr: impl Into<u16>,
@@ -88,6 +177,24 @@ impl SequencerFpga {
         self.raw_spi_write(Cmd::BitClear, addr.into(), data_in)
     }
 
+    /// Updates the bits selected by `mask` in the byte at `addr` to match
+    /// `value`, leaving the others alone. Unlike `set_bytes`/`clear_bytes`,
+    /// this can set some bits and clear others in a single call; note that
+    /// it's a read followed by a write, so it isn't atomic with respect to
+    /// the FPGA.
+    pub fn modify(
+        &self,
+        addr: impl Into<u16>,
+        mask: u8,
+        value: u8,
+    ) -> Result<(), spi_api::SpiError> {
+        let addr = addr.into();
+        let mut byte = [0u8];
+        self.read_bytes(addr, &mut byte)?;
+        byte[0] = (byte[0] & !mask) | (value & mask);
+        self.write_bytes(addr, &byte)
+    }
+
     /// Performs a read-shaped transaction using an arbitrary command and any
     /// address. It's important that `cmd` is one that ignores data sent by us
     /// after the address, or this will overwrite `addr` with arbitrary data.
@@ -97,8 +204,10 @@ impl SequencerFpga {
         addr: u16,
         data_out: &mut [u8],
     ) -> Result<(), spi_api::SpiError> {
-        let mut data = [0u8; 16];
-        let mut rval = [0u8; 16];
+        check_transfer(addr, data_out.len())?;
+
+        let mut data = [0u8; TRANSFER_SIZE];
+        let mut rval = [0u8; TRANSFER_SIZE];
 
         let addr = U16::new(addr);
         let header = CmdHeader { cmd, addr };
@@ -110,11 +219,7 @@ impl SequencerFpga {
 
         self.spi.exchange(&data, &mut rval)?;
 
-        for i in 0..data_out.len() {
-            if i + header.len() < data.len() {
-                data_out[i] = rval[i + header.len()];
-            }
-        }
+        data_out.copy_from_slice(&rval[header.len()..][..data_out.len()]);
 
         Ok(())
     }
@@ -127,8 +232,10 @@ impl SequencerFpga {
         addr: u16,
         data_in: &[u8],
     ) -> Result<(), spi_api::SpiError> {
-        let mut data = [0u8; 16];
-        let mut rval = [0u8; 16];
+        check_transfer(addr, data_in.len())?;
+
+        let mut data = [0u8; TRANSFER_SIZE];
+        let mut rval = [0u8; TRANSFER_SIZE];
 
         let addr = U16::new(addr);
         let header = CmdHeader { cmd, addr };
@@ -138,11 +245,7 @@ impl SequencerFpga {
             data[i] = header[i];
         }
 
-        for i in 0..data_in.len() {
-            if i + header.len() < data.len() {
-                data[i + header.len()] = data_in[i];
-            }
-        }
+        data[header.len()..][..data_in.len()].copy_from_slice(data_in);
 
         self.spi.exchange(&data, &mut rval)?;
 
@@ -150,6 +253,15 @@ impl SequencerFpga {
     }
 }
 
+/// Rejects transfers that won't fit in a single command, or that would run
+/// off the end of the FPGA's 16-bit address space.
+fn check_transfer(addr: u16, len: usize) -> Result<(), spi_api::SpiError> {
+    if len > MAX_DATA_SIZE || usize::from(addr) + len > 1 << 16 {
+        return Err(spi_api::SpiError::BadTransferSize);
+    }
+    Ok(())
+}
+
 #[derive(AsBytes, Unaligned)]
 #[repr(C)]
 struct CmdHeader {
@@ -3,7 +3,7 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 #![no_std]
 
-use drv_spi_api::{SpiDevice, SpiError};
+use drv_spi_api::{ControllerLock, CsState, SpiDevice, SpiError};
 use ringbuf::*;
 use userlib::hl::sleep_for;
 
@@ -16,6 +16,19 @@ pub use registers::{MIBCounter, Register};
 pub enum Error {
     SpiError(SpiError),
     WrongChipId(u16),
+    /// `CIDER` read back as all zeros or all ones, or changed between two
+    /// reads.  This usually means that nothing is talking on the bus, e.g.
+    /// because of SPI wiring or a misconfigured SPI mode, rather than that a
+    /// different chip is present.
+    NoResponse(u16, u16),
+    /// The enable bit in `CIDER` did not stick, even after a retry (and,
+    /// from `configure_with_retry`, after a soft reset and reconfiguration)
+    EnableFailed,
+    /// A register written by `apply_switch_config` did not read back with
+    /// the expected value
+    ConfigVerifyFailed(Register),
+    /// The dynamic MAC table flush bit never cleared itself
+    FlushTimeout,
 }
 
 impl From<SpiError> for Error {
@@ -24,6 +37,7 @@ impl From<SpiError> for Error {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum VLanMode {
     /// Configure VLAN tags 0x301 and 0x302 for (upstream) ports 1 and 2
     /// respectively.  Allow untagged frames on any port, but drop tagged
@@ -41,6 +55,7 @@ pub enum VLanMode {
     Off,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Mode {
     /// 10/100BASE-TX mode
     Copper,
@@ -48,6 +63,44 @@ pub enum Mode {
     Fiber,
 }
 
+/// Board-specific switch configuration, applied by `apply_switch_config`
+/// after the chip has been configured with `configure`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SwitchConfig {
+    /// Tail tagging on port 3, which lets the SP see (and choose) the
+    /// upstream port for each frame; see `Ksz8463::set_tail_tagging`.
+    pub tail_tag: bool,
+    /// Switch-wide enable for acting on received pause frames; see
+    /// `Ksz8463::set_flow_control`.
+    pub rx_flow_control: bool,
+    /// Switch-wide enable for sending pause frames
+    pub tx_flow_control: bool,
+    /// Forced flow control for ports 1-3
+    pub flow_control: [bool; 3],
+    /// Address learning for ports 1-3
+    pub learning: [bool; 3],
+}
+
+impl Default for SwitchConfig {
+    /// Matches the chip's reset values
+    fn default() -> Self {
+        Self {
+            tail_tag: false,
+            rx_flow_control: true,
+            tx_flow_control: true,
+            flow_control: [false; 3],
+            learning: [true; 3],
+        }
+    }
+}
+
+/// SGCR1 bits that turn off IEEE 802.3x flow control for the whole switch
+const RX_FLOW_CONTROL_DISABLE: u16 = 1 << 12;
+const TX_FLOW_CONTROL_DISABLE: u16 = 1 << 13;
+
+/// PxCR2 bit that forces flow control on for a port
+const FORCE_FLOW_CONTROL: u16 = 1 << 12;
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -55,7 +108,20 @@ enum Trace {
     None,
     Read(Register, u16),
     Write(Register, u16),
+    ReadFailed(Register, SpiError),
+    WriteFailed(Register, SpiError),
     Id(u16),
+    SoftReset,
+    Reconfigure,
+    Enable {
+        attempt: u8,
+        enabled: bool,
+    },
+    VerifyFailed {
+        reg: Register,
+        mask: u16,
+        value: u16,
+    },
 }
 ringbuf!(Trace, 16, Trace::None);
 
@@ -132,7 +198,10 @@ impl Ksz8463 {
         let cmd = Self::pack_addr(r as u16).to_be_bytes();
         let mut response = [0; 4];
 
-        self.spi.exchange(&cmd, &mut response)?;
+        self.spi.exchange(&cmd, &mut response).map_err(|e| {
+            ringbuf_entry!(Trace::ReadFailed(r, e));
+            e
+        })?;
         let v = u16::from_le_bytes(response[2..].try_into().unwrap());
         ringbuf_entry!(Trace::Read(r, v));
 
@@ -149,16 +218,34 @@ impl Ksz8463 {
         request[0] |= 0x80; // Set MSB to indicate write.
 
         ringbuf_entry!(Trace::Write(r, v));
-        self.spi.write(&request[..])?;
+        self.spi.write(&request[..]).map_err(|e| {
+            ringbuf_entry!(Trace::WriteFailed(r, e));
+            e
+        })?;
         Ok(())
     }
 
+    /// Locks the SPI controller to this device until the returned guard is
+    /// dropped, so that a sequence of dependent transactions can't have
+    /// another task's traffic interleaved into it.  Chip select still
+    /// toggles for each transaction.
+    ///
+    /// This is used by `modify` and by the indirect-access operations (MIB
+    /// counters, the MAC table, and the VLAN table), which depend on several
+    /// transactions in a row.  The lock doesn't nest: taking it again and
+    /// dropping the inner guard releases it entirely, so functions which hold
+    /// it must not call each other.
+    fn lock(&self) -> Result<ControllerLock, Error> {
+        Ok(self.spi.lock_auto(CsState::NotAsserted)?)
+    }
+
     /// Performs a read-modify-write operation on a PHY register
     #[inline(always)]
     pub fn modify<F>(&self, reg: Register, f: F) -> Result<(), Error>
     where
         F: Fn(&mut u16),
     {
+        let _lock = self.lock()?;
         let mut data = self.read(reg)?;
         f(&mut data);
         self.write(reg, data)
@@ -176,6 +263,188 @@ impl Ksz8463 {
         self.write(Register::CIDER, 0)
     }
 
+    /// Enables the switch, then reads back `CIDER` to confirm that the enable
+    /// bit actually took.  If the switch was left in an odd state (e.g. by a
+    /// warm SP reset), the first attempt may not stick, so we cycle through
+    /// `disable` and try once more before giving up.
+    pub fn enable_verified(&self) -> Result<(), Error> {
+        for attempt in 0..2 {
+            if attempt > 0 {
+                self.disable()?;
+                sleep_for(1);
+            }
+            self.enable()?;
+            let enabled = self.enabled()?;
+            ringbuf_entry!(Trace::Enable { attempt, enabled });
+            if enabled {
+                return Ok(());
+            }
+        }
+        Err(Error::EnableFailed)
+    }
+
+    /// Enables or disables both transmit and receive on a port, which must be
+    /// 1, 2, or 3; otherwise, this function will panic.
+    pub fn set_port_enabled(
+        &self,
+        port: u8,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let mask = (1 << 10) | (1 << 9); // TX enable, RX enable
+        self.modify(Self::port_cr2(port), |r| {
+            if enabled {
+                *r |= mask;
+            } else {
+                *r &= !mask;
+            }
+        })
+    }
+
+    /// Checks whether a port has both transmit and receive enabled.  `port`
+    /// must be 1, 2, or 3; otherwise, this function will panic.
+    pub fn port_enabled(&self, port: u8) -> Result<bool, Error> {
+        let mask = (1 << 10) | (1 << 9); // TX enable, RX enable
+        Ok(self.read(Self::port_cr2(port))? & mask == mask)
+    }
+
+    /// Returns the Port Control 2 register for any of the three ports.
+    /// `Register::PxCR2` only covers the upstream ports.
+    fn port_cr2(port: u8) -> Register {
+        match port {
+            3 => Register::P3CR2,
+            _ => Register::PxCR2(port),
+        }
+    }
+
+    /// Sets or clears the bits in `mask`, then reads the register back to
+    /// confirm that the write took.
+    fn write_bits_verified(
+        &self,
+        reg: Register,
+        mask: u16,
+        set: bool,
+    ) -> Result<(), Error> {
+        self.modify(reg, |r| {
+            if set {
+                *r |= mask;
+            } else {
+                *r &= !mask;
+            }
+        })?;
+        let v = self.read(reg)? & mask;
+        if v != if set { mask } else { 0 } {
+            ringbuf_entry!(Trace::VerifyFailed {
+                reg,
+                mask,
+                value: v
+            });
+            return Err(Error::ConfigVerifyFailed(reg));
+        }
+        Ok(())
+    }
+
+    /// Flushes every learned entry from the dynamic MAC table, so that the
+    /// switch re-learns addresses (e.g. after a cabling change) rather than
+    /// forwarding to stale ports until they age out.  Static entries are left
+    /// alone.
+    pub fn flush_dynamic_mac_table(&self) -> Result<(), Error> {
+        // SGCR1 bit 5 triggers the flush, and clears itself once it's done.
+        const FLUSH_DYNAMIC: u16 = 1 << 5;
+        self.modify(Register::SGCR1, |r| *r |= FLUSH_DYNAMIC)?;
+        for _ in 0..10 {
+            if self.read(Register::SGCR1)? & FLUSH_DYNAMIC == 0 {
+                return Ok(());
+            }
+            sleep_for(1);
+        }
+        Err(Error::FlushTimeout)
+    }
+
+    /// Enables or disables tail tagging on port 3 (the SP's port).
+    ///
+    /// With tail tagging on, every frame between the switch and the SP
+    /// carries one extra byte at its very end (after the payload and any
+    /// padding, but before the FCS):
+    ///
+    /// - On frames from the switch, it says which port the frame came in on:
+    ///   0 for port 1, or 1 for port 2.
+    /// - On frames to the switch, it's a bitmask of the ports to send the
+    ///   frame out of: bit 0 for port 1, bit 1 for port 2.  If it's zero, the
+    ///   switch forwards the frame as usual, by looking up its destination.
+    ///
+    /// The netstack must add and strip this byte itself, so this has to be
+    /// turned on or off in lockstep with it.
+    pub fn set_tail_tagging(&self, enable: bool) -> Result<(), Error> {
+        self.write_bits_verified(Register::SGCR3, 1 << 8, enable)
+    }
+
+    /// Enables or disables IEEE 802.3x flow control.  With `rx` set, the
+    /// switch stops transmitting on a port that sends it a pause frame; with
+    /// `tx` set, it sends pause frames when its own buffers fill.
+    ///
+    /// The chip only has switch-wide enables for these (in SGCR1, with the
+    /// inverted sense), so they apply to every port.  Whether a port uses
+    /// flow control at all is then down to autonegotiation, unless it's
+    /// forced on with `set_forced_flow_control`.
+    pub fn set_flow_control(&self, rx: bool, tx: bool) -> Result<(), Error> {
+        self.write_bits_verified(
+            Register::SGCR1,
+            RX_FLOW_CONTROL_DISABLE,
+            !rx,
+        )?;
+        self.write_bits_verified(Register::SGCR1, TX_FLOW_CONTROL_DISABLE, !tx)
+    }
+
+    /// Reads back the switch-wide flow control enables, as `(rx, tx)`; see
+    /// `set_flow_control`.
+    pub fn flow_control(&self) -> Result<(bool, bool), Error> {
+        let r = self.read(Register::SGCR1)?;
+        Ok((
+            r & RX_FLOW_CONTROL_DISABLE == 0,
+            r & TX_FLOW_CONTROL_DISABLE == 0,
+        ))
+    }
+
+    /// Forces flow control on for a port (which must be 1, 2, or 3;
+    /// otherwise, this function will panic) regardless of what was
+    /// negotiated, or leaves it to autonegotiation.
+    pub fn set_forced_flow_control(
+        &self,
+        port: u8,
+        force: bool,
+    ) -> Result<(), Error> {
+        self.write_bits_verified(
+            Self::port_cr2(port),
+            FORCE_FLOW_CONTROL,
+            force,
+        )
+    }
+
+    /// Checks whether flow control is forced on for a port, which must be 1,
+    /// 2, or 3; otherwise, this function will panic.
+    pub fn forced_flow_control(&self, port: u8) -> Result<bool, Error> {
+        Ok(self.read(Self::port_cr2(port))? & FORCE_FLOW_CONTROL != 0)
+    }
+
+    /// Applies the board-specific parts of switch configuration, verifying
+    /// each register after it's written.  This should be called after
+    /// `configure`, which resets the chip and would discard these settings.
+    pub fn apply_switch_config(&self, cfg: &SwitchConfig) -> Result<(), Error> {
+        self.set_tail_tagging(cfg.tail_tag)?;
+        self.set_flow_control(cfg.rx_flow_control, cfg.tx_flow_control)?;
+
+        for (i, port) in (1..=3).enumerate() {
+            self.set_forced_flow_control(port, cfg.flow_control[i])?;
+            // Learning disable (note the inverted sense)
+            self.write_bits_verified(
+                Self::port_cr2(port),
+                1 << 8,
+                !cfg.learning[i],
+            )?;
+        }
+        Ok(())
+    }
+
     /// Reads a management information base (MIB) counter
     ///
     /// `port` must be 1 or 2 to select the relevant port; otherwise, this
@@ -190,6 +459,8 @@ impl Ksz8463 {
             2 => 0x20,
             _ => panic!("Invalid port {}", port),
         };
+        let _lock = self.lock()?;
+
         // Request counter with given offset.
         self.write(
             Register::IACR,
@@ -227,6 +498,7 @@ impl Ksz8463 {
         addr: u16,
     ) -> Result<MacTableEntry, Error> {
         assert!(addr < 1024);
+        let _lock = self.lock()?;
         self.write(Register::IACR, 0x1800 | addr)?;
         // Wait for the "not ready" bit to be cleared
         let d_71_64 = loop {
@@ -296,6 +568,7 @@ impl Ksz8463 {
         assert!(port_mask <= 0b111);
         assert!(vlan_id <= 4096);
 
+        let _lock = self.lock()?;
         let cmd = vlan_id as u32
             | (u32::from(true) << 19) // valid
             | (u32::from(port_mask) << 16) // ports
@@ -308,18 +581,60 @@ impl Ksz8463 {
     /// Disables an entry in the VLAN table.  This is particularly important
     /// to disable VLAN 1, which otherwise is allowed on all ports.
     fn disable_vlan(&self, table_entry: u8) -> Result<(), Error> {
+        let _lock = self.lock()?;
         self.write(Register::IADR5, 0)?;
         self.write(Register::IADR4, 0)?;
         self.write(Register::IACR, 0x400 | u16::from(table_entry))
     }
 
+    /// Performs a global software reset of the chip, which puts its registers
+    /// back into their power-on state.
+    pub fn soft_reset(&self) -> Result<(), Error> {
+        ringbuf_entry!(Trace::SoftReset);
+        self.write(Register::GRR, 1)?;
+        sleep_for(10);
+        self.write(Register::GRR, 0)
+    }
+
+    /// Configures the switch with [Ksz8463::configure]; if the enable bit
+    /// doesn't stick at the end, soft-resets the chip and configures it once
+    /// more before giving up.
+    ///
+    /// This is for boards where the SP can restart without the switch losing
+    /// power, which can leave the switch in a state that survives the usual
+    /// `disable`/`enable` cycle.
+    pub fn configure_with_retry(
+        &self,
+        mode: Mode,
+        vlan_mode: VLanMode,
+    ) -> Result<(), Error> {
+        match self.configure(mode, vlan_mode) {
+            Err(Error::EnableFailed) => {
+                // `configure` begins with a soft reset, so this resets the
+                // chip before rewriting every register.
+                ringbuf_entry!(Trace::Reconfigure);
+                self.configure(mode, vlan_mode)
+            }
+            r => r,
+        }
+    }
+
     /// Configures the KSZ8463 switch in 100BASE-FX mode.
     pub fn configure(
         &self,
         mode: Mode,
         vlan_mode: VLanMode,
     ) -> Result<(), Error> {
-        let id = self.read(Register::CIDER)? & !1;
+        // Read the ID twice, so that we can tell a floating MISO line (which
+        // tends to read as all zeros or all ones, or as noise) apart from a
+        // chip that's actually responding with the wrong ID.
+        let a = self.read(Register::CIDER)?;
+        let b = self.read(Register::CIDER)?;
+        if a != b || a == 0 || a == 0xFFFF {
+            return Err(Error::NoResponse(a, b));
+        }
+
+        let id = a & !1;
         ringbuf_entry!(Trace::Id(id));
         if id != 0x8452 {
             return Err(Error::WrongChipId(id));
@@ -327,15 +642,15 @@ impl Ksz8463 {
 
         // Do a full software reset of the chip to put registers into
         // a known state.
-        self.write(Register::GRR, 1)?;
-        sleep_for(10);
-        self.write(Register::GRR, 0)?;
+        self.soft_reset()?;
 
         match mode {
             Mode::Fiber => {
-                // Configure for 100BASE-FX operation
-                self.modify(Register::CFGR, |r| *r &= !0xc0)?;
-                self.modify(Register::DSP_CNTRL_6, |r| *r &= !0x2000)?;
+                // Configure for 100BASE-FX operation.  These writes are
+                // ignored if the switch is in a bad state (e.g. after a warm
+                // restart), so read them back rather than trusting them.
+                self.write_bits_verified(Register::CFGR, 0xc0, false)?;
+                self.write_bits_verified(Register::DSP_CNTRL_6, 0x2000, false)?;
             }
             Mode::Copper => (), // No changes from defaults
         }
@@ -437,6 +752,6 @@ impl Ksz8463 {
             VLanMode::Off => (),
         }
 
-        self.enable()
+        self.enable_verified()
     }
 }
@@ -7,20 +7,68 @@
 #![no_std]
 
 use derive_idol_err::IdolError;
+use serde::{Deserialize, Serialize};
 use userlib::*;
-use zerocopy::AsBytes;
+use zerocopy::{AsBytes, FromBytes};
 
-#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, IdolError)]
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    FromPrimitive,
+    PartialEq,
+    IdolError,
+    Serialize,
+    Deserialize,
+)]
 pub enum SeqError {
     IllegalTransition = 1,
     ClockConfigFailed = 2,
+    ClocksNotConfigured = 3,
 }
 
-#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, AsBytes)]
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    FromPrimitive,
+    PartialEq,
+    AsBytes,
+    Serialize,
+    Deserialize,
+)]
 #[repr(u8)]
 pub enum PowerState {
     A2 = 1,
     A0 = 2,
 }
 
+/// Snapshot of sequencer state, returned by `get_seq_diagnostics` so that a
+/// monitor can get everything in one round-trip.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct SeqDiagnostics {
+    /// Kernel time at which the snapshot was taken
+    pub timestamp: u64,
+    pub state: PowerState,
+    pub clock_config_loaded: bool,
+    /// Attempts made by the most recent `load_clock_config`, including the
+    /// one that succeeded (if any), or 0 if it hasn't been run
+    pub clock_config_attempts: u8,
+    /// Most recent error (see `get_last_error`)
+    pub last_error: Option<SeqError>,
+    /// Kernel time at which `last_error` was latched, or 0 if there isn't one
+    pub last_error_time: u64,
+}
+
+/// How many times each `SeqError` has come up since the sequencer started (or
+/// since `clear_error_counts`), returned by `get_error_counts`. Counts stick
+/// at `u32::MAX` rather than wrapping.
+#[derive(Copy, Clone, Debug, Default, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct ErrorCounts {
+    pub illegal_transition: u32,
+    pub clock_config_failed: u32,
+    pub clocks_not_configured: u32,
+}
+
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
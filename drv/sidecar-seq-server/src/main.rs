@@ -3,6 +3,14 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Server for managing the Sidecar sequencing process.
+//!
+//! Our only bus traffic is I2C to the clock generator. The I2C server
+//! recovers a hung bus itself and reports it as an error, which
+//! `load_clock_config` latches as `SeqError::ClockConfigFailed`, so nothing
+//! here can wait on the hardware indefinitely.
+//!
+//! As with gimlet-seq-server, there's no standalone build; we only build for
+//! the target.
 
 #![no_std]
 #![no_main]
@@ -11,7 +19,7 @@ use ringbuf::*;
 use userlib::*;
 
 use drv_i2c_api::{I2cDevice, ResponseCode};
-use drv_sidecar_seq_api::{PowerState, SeqError};
+use drv_sidecar_seq_api::{ErrorCounts, PowerState, SeqDiagnostics, SeqError};
 use idol_runtime::{NotificationHandler, RequestError};
 
 task_slot!(SYS, sys);
@@ -27,10 +35,14 @@ enum Trace {
     A2,
     GetState,
     SetState(PowerState, PowerState),
-    LoadClockConfig,
+    LoadClockConfig(u8),
     ClockConfigWrite(usize),
     ClockConfigSuccess(usize),
     ClockConfigFailed(usize, ResponseCode),
+    LatchedError(SeqError),
+    AutoClockConfig,
+    LedLocate(bool),
+    TimerBehind(u64),
     Done,
     None,
 }
@@ -38,25 +50,132 @@ enum Trace {
 ringbuf!(Trace, 64, Trace::None);
 
 const TIMER_MASK: u32 = 1 << 0;
+
+/// Period of our timer (which drives the heartbeat LED), in ms
 const TIMER_INTERVAL: u64 = 1000;
 
+/// The heartbeat LED, and whether driving its pin high turns it on.
+const LED: drv_stm32xx_sys_api::PinSet = drv_stm32xx_sys_api::Port::C.pin(3);
+const LED_ACTIVE_HIGH: bool = true;
+
+/// If set, an A2 -> A0 transition loads the clock configuration itself when
+/// nobody has called `load_clock_config` yet, rather than failing with
+/// `ClocksNotConfigured`.
+const AUTO_CLOCK_CONFIG: bool = false;
+
+/// Every transition that `set_state` will consider; anything else is refused
+/// with `IllegalTransition` before we look at (or touch) the hardware. New
+/// states, such as A1, get their transitions added here first.
+///
+/// There's no minimum dwell time between transitions: none of them touches
+/// the hardware yet (see `ServerImpl::transition`), so there's nothing to
+/// protect. One should come with the A2 -> A0 sequencing, so that a flapping
+/// caller can't cycle the ASIC's regulators faster than they can cope with.
+const TRANSITIONS: &[(PowerState, PowerState)] = &[
+    (PowerState::A2, PowerState::A0),
+    (PowerState::A0, PowerState::A2),
+];
+
+fn transition_allowed(from: PowerState, to: PowerState) -> bool {
+    TRANSITIONS.contains(&(from, to))
+}
+
+/// Number of times `load_clock_config` runs the whole payload before giving
+/// up. Each attempt starts over from the top, so it doesn't matter how far a
+/// failed attempt (or one cut short by our restart) got.
+const CLOCK_CONFIG_ATTEMPTS: u8 = 3;
+
 struct ServerImpl {
     state: PowerState,
     clockgen: I2cDevice,
     led: drv_stm32xx_sys_api::PinSet,
     led_on: bool,
+    led_locate: bool,
     deadline: u64,
     clock_config_loaded: bool,
+    clock_config_attempts: u8,
+    last_error: Option<SeqError>,
+    last_error_time: u64,
+    error_counts: ErrorCounts,
 }
 
 impl ServerImpl {
+    //
+    // Records the most recent error so that it can be retrieved later via
+    // `get_last_error`, even if the caller that hit it didn't report it, and
+    // counts it. Every error we return goes through here.
+    //
+    fn latch(&mut self, err: SeqError) -> RequestError<SeqError> {
+        ringbuf_entry!(Trace::LatchedError(err));
+        self.last_error = Some(err);
+        self.last_error_time = sys_get_timer().now;
+
+        let counts = &mut self.error_counts;
+        let count = match err {
+            SeqError::IllegalTransition => &mut counts.illegal_transition,
+            SeqError::ClockConfigFailed => &mut counts.clock_config_failed,
+            SeqError::ClocksNotConfigured => &mut counts.clocks_not_configured,
+        };
+        *count = count.saturating_add(1);
+        RequestError::Runtime(err)
+    }
+
+    //
+    // In debug builds, checks that what we've done to the hardware agrees
+    // with `state`, so that the two can't quietly drift apart as transitions
+    // are added. For now, the only thing that a state asks of us is that A0
+    // have a clock configuration under it. We can't reach A0 yet (see
+    // `transition`), so this is in place for when the A2 -> A0 sequencing
+    // lands rather than catching anything today.
+    //
+    fn check_state(&self) {
+        debug_assert!(
+            self.state != PowerState::A0 || self.clock_config_loaded,
+            "in A0 without a clock configuration"
+        );
+    }
+
+    fn transition(
+        &mut self,
+        msg: &RecvMessage,
+        state: PowerState,
+    ) -> Result<(), RequestError<SeqError>> {
+        if !transition_allowed(self.state, state) {
+            return Err(self.latch(SeqError::IllegalTransition));
+        }
+
+        if AUTO_CLOCK_CONFIG
+            && !self.clock_config_loaded
+            && (self.state, state) == (PowerState::A2, PowerState::A0)
+        {
+            ringbuf_entry!(Trace::AutoClockConfig);
+            idl::InOrderSequencerImpl::load_clock_config(self, msg)?;
+        }
+
+        match (self.state, state) {
+            //
+            // Without a clock configuration, the Tofino sequencer will never
+            // make it out of A2; refuse up front rather than letting the
+            // caller discover this as a timeout.
+            //
+            (PowerState::A2, PowerState::A0) if !self.clock_config_loaded => {
+                Err(self.latch(SeqError::ClocksNotConfigured))
+            }
+            //
+            // Legal, but we can't sequence the Tofino yet.
+            //
+            _ => Err(self.latch(SeqError::IllegalTransition)),
+        }
+    }
+
     fn led_init(&mut self) {
         use drv_stm32xx_sys_api::*;
 
         let sys = SYS.get_task_id();
         let sys = Sys::from(sys);
 
-        // Make the LED an output.
+        // Make the LED an output, starting out off.
+        sys.gpio_set_to(self.led, !LED_ACTIVE_HIGH).unwrap();
         sys.gpio_configure_output(
             self.led,
             OutputType::PushPull,
@@ -71,7 +190,7 @@ impl ServerImpl {
 
         let sys = SYS.get_task_id();
         let sys = Sys::from(sys);
-        sys.gpio_set_to(self.led, true).unwrap();
+        sys.gpio_set_to(self.led, LED_ACTIVE_HIGH).unwrap();
         self.led_on = true;
     }
 
@@ -80,7 +199,7 @@ impl ServerImpl {
 
         let sys = SYS.get_task_id();
         let sys = Sys::from(sys);
-        sys.gpio_set_to(self.led, false).unwrap();
+        sys.gpio_set_to(self.led, !LED_ACTIVE_HIGH).unwrap();
         self.led_on = false;
     }
 
@@ -104,49 +223,150 @@ impl idl::InOrderSequencerImpl for ServerImpl {
 
     fn set_state(
         &mut self,
-        _: &RecvMessage,
+        msg: &RecvMessage,
         state: PowerState,
     ) -> Result<(), RequestError<SeqError>> {
         ringbuf_entry!(Trace::SetState(self.state, state));
 
-        match (self.state, state) {
-            _ => Err(RequestError::Runtime(SeqError::IllegalTransition)),
+        // Whether or not the transition went through, we should be wholly in
+        // one state or the other.
+        let result = self.transition(msg, state);
+        self.check_state();
+        result
+    }
+
+    fn ensure_state(
+        &mut self,
+        msg: &RecvMessage,
+        state: PowerState,
+    ) -> Result<PowerState, RequestError<SeqError>> {
+        //
+        // Unlike `set_state`, being in the target state already isn't an
+        // error; anything else goes through the usual transition.
+        //
+        if self.state != state {
+            idl::InOrderSequencerImpl::set_state(self, msg, state)?;
         }
+        Ok(self.state)
     }
 
     fn load_clock_config(
         &mut self,
         _: &RecvMessage,
     ) -> Result<(), RequestError<SeqError>> {
-        ringbuf_entry!(Trace::LoadClockConfig);
-
-        let mut packet = 0;
-
-        payload::idt8a3xxxx_payload(|buf| {
-            ringbuf_entry!(Trace::ClockConfigWrite(packet));
-            match self.clockgen.write(buf) {
-                Err(err) => {
-                    ringbuf_entry!(Trace::ClockConfigFailed(packet, err));
-                    Err(SeqError::ClockConfigFailed)
+        //
+        // The payload is nothing but absolute register writes, and it opens
+        // by setting the page register, so it can be run over a partially
+        // programmed part (say, one we were configuring when we restarted)
+        // and still converge. Until a run completes, though, we can't claim
+        // the clocks are configured, even if an earlier run finished.
+        //
+        self.clock_config_loaded = false;
+
+        let mut result = Ok(());
+
+        for attempt in 0..CLOCK_CONFIG_ATTEMPTS {
+            ringbuf_entry!(Trace::LoadClockConfig(attempt));
+            self.clock_config_attempts = attempt + 1;
+
+            let mut packet = 0;
+
+            result = payload::idt8a3xxxx_payload(|buf| {
+                ringbuf_entry!(Trace::ClockConfigWrite(packet));
+                match self.clockgen.write(buf) {
+                    Err(err) => {
+                        ringbuf_entry!(Trace::ClockConfigFailed(packet, err));
+                        Err(SeqError::ClockConfigFailed)
+                    }
+
+                    Ok(_) => {
+                        ringbuf_entry!(Trace::ClockConfigSuccess(packet));
+                        packet += 1;
+                        Ok(())
+                    }
                 }
+            });
 
-                Ok(_) => {
-                    ringbuf_entry!(Trace::ClockConfigSuccess(packet));
-                    packet += 1;
-                    Ok(())
-                }
+            if result.is_ok() {
+                break;
             }
-        })?;
+        }
+
+        result.map_err(|err| self.latch(err))?;
         self.clock_config_loaded = true;
 
         Ok(())
     }
+
     fn is_clock_config_loaded(
         &mut self,
         _: &RecvMessage,
     ) -> Result<u8, RequestError<SeqError>> {
         Ok(self.clock_config_loaded as u8)
     }
+
+    fn get_last_error(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<Option<SeqError>, RequestError<SeqError>> {
+        Ok(self.last_error)
+    }
+
+    fn get_seq_diagnostics(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<SeqDiagnostics, RequestError<SeqError>> {
+        Ok(SeqDiagnostics {
+            timestamp: sys_get_timer().now,
+            state: self.state,
+            clock_config_loaded: self.clock_config_loaded,
+            clock_config_attempts: self.clock_config_attempts,
+            last_error: self.last_error,
+            last_error_time: self.last_error_time,
+        })
+    }
+
+    fn clear_last_error(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<SeqError>> {
+        self.last_error = None;
+        self.last_error_time = 0;
+        Ok(())
+    }
+
+    fn get_error_counts(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<ErrorCounts, RequestError<SeqError>> {
+        Ok(self.error_counts)
+    }
+
+    fn clear_error_counts(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<SeqError>> {
+        self.error_counts = ErrorCounts::default();
+        Ok(())
+    }
+
+    fn set_led_locate(
+        &mut self,
+        _: &RecvMessage,
+        on: u8,
+    ) -> Result<(), RequestError<SeqError>> {
+        ringbuf_entry!(Trace::LedLocate(on != 0));
+        self.led_locate = on != 0;
+
+        //
+        // Light the LED now rather than waiting for the next heartbeat; when
+        // clearing, the heartbeat picks up again on its own.
+        //
+        if self.led_locate {
+            self.led_on();
+        }
+        Ok(())
+    }
 }
 
 impl NotificationHandler for ServerImpl {
@@ -156,7 +376,24 @@ impl NotificationHandler for ServerImpl {
 
     fn handle_notification(&mut self, _bits: u32) {
         self.deadline += TIMER_INTERVAL;
-        self.led_toggle();
+
+        //
+        // If we were busy for long enough to miss a whole tick (e.g. in a
+        // slow `set_state`), start again from now rather than firing a burst
+        // of catch-up ticks.
+        //
+        let now = sys_get_timer().now;
+        if self.deadline <= now {
+            ringbuf_entry!(Trace::TimerBehind(now - self.deadline));
+            self.deadline = now + TIMER_INTERVAL;
+        }
+
+        // Locate mode takes priority over the heartbeat.
+        if self.led_locate {
+            self.led_on();
+        } else {
+            self.led_toggle();
+        }
         sys_set_timer(Some(self.deadline), TIMER_MASK);
     }
 }
@@ -179,10 +416,15 @@ fn main() -> ! {
     let mut server = ServerImpl {
         state: PowerState::A2,
         clockgen: devices::idt8a34001(task)[0],
-        led: drv_stm32xx_sys_api::Port::C.pin(3),
+        led: LED,
         led_on: false,
+        led_locate: false,
         deadline,
         clock_config_loaded: false,
+        clock_config_attempts: 0,
+        last_error: None,
+        last_error_time: 0,
+        error_counts: ErrorCounts::default(),
     };
 
     server.led_init();
@@ -194,7 +436,7 @@ fn main() -> ! {
 }
 
 mod idl {
-    use super::{PowerState, SeqError};
+    use super::{ErrorCounts, PowerState, SeqDiagnostics, SeqError};
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -203,3 +203,16 @@ impl Default for Counter {
         Self::Inactive
     }
 }
+
+/// Per-port error counters, as read by
+/// [vsc85x2::Vsc85x2Phy::error_counters].  Each is an 8-bit counter which
+/// saturates rather than wrapping.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ErrorCounters {
+    /// Receive errors (Error Counter 1)
+    pub rx_errors: u8,
+    /// False carrier events (Error Counter 2)
+    pub false_carrier: u8,
+    /// Link disconnects (Error Counter 3)
+    pub link_disconnects: u8,
+}
@@ -22,6 +22,18 @@ impl<'a, P: PhyRw> Phy<'a, P> {
         self.wait_timeout(phy::STANDARD::MODE_CONTROL(), |r| r.sw_reset() != 1)
     }
 
+    /// Software-resets this port and the one after it, for two-port PHYs
+    /// which can't do a broadcast reset.  The second port is reset even if
+    /// the first one fails, so that the caller can decide whether one working
+    /// port is good enough.
+    pub(crate) fn reset_port_pair(&mut self) -> [Result<(), VscError>; 2] {
+        let mut out = [Ok(()); 2];
+        for (p, r) in out.iter_mut().enumerate() {
+            *r = Phy::new(self.port + p as u8, self.rw).software_reset();
+        }
+        out
+    }
+
     /// The VSC85xx family supports sending commands to the system by writing to
     /// register 19G.  This helper function sends a command then waits for it
     /// to finish, return [VscError::PhyInitTimeout] if it fails (or another
@@ -14,7 +14,9 @@ pub struct Vsc8552Phy<'a, 'b, P> {
 impl<'a, 'b, P: PhyRw> Vsc8552Phy<'a, 'b, P> {
     /// Initializes a VSC8552 PHY using SGMII based on section 3.1.2 (2x SGMII
     /// to 100BASE-FX SFP Fiber).  Same caveats as `init` apply.
-    pub fn init(&mut self) -> Result<(), VscError> {
+    ///
+    /// On success, returns the result of resetting each of the two ports.
+    pub fn init(&mut self) -> Result<[Result<(), VscError>; 2], VscError> {
         ringbuf_entry!(Trace::Vsc8552Init(self.phy.port));
         self.phy.check_base_port()?;
 
@@ -68,9 +70,6 @@ impl<'a, 'b, P: PhyRw> Vsc8552Phy<'a, 'b, P> {
 
         // Now, we reset the PHY to put those settings into effect.  For some
         // reason, we can't do a broadcast reset, so we do it port-by-port.
-        for p in 0..2 {
-            Phy::new(self.phy.port + p, self.phy.rw).software_reset()?;
-        }
-        Ok(())
+        Ok(self.phy.reset_port_pair())
     }
 }
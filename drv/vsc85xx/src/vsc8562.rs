@@ -17,7 +17,9 @@ pub struct Vsc8562Phy<'a, 'b, P> {
 impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
     /// Initializes a VSC8562 PHY using SGMII based on section 3.1.2.1 (2x SGMII
     /// to 100BASE-FX SFP Fiber).  Same caveats as `init` apply.
-    pub fn init(&mut self) -> Result<(), VscError> {
+    ///
+    /// On success, returns the result of resetting each of the two ports.
+    pub fn init(&mut self) -> Result<[Result<(), VscError>; 2], VscError> {
         // This is roughly based on `vtss_phy_reset_private`
         ringbuf_entry!(Trace::Vsc8562Init(self.phy.port));
         self.phy.check_base_port()?;
@@ -54,9 +56,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
 
         // Now, we reset the PHY to put those settings into effect.  For some
         // reason, we can't do a broadcast reset, so we do it port-by-port.
-        for p in 0..2 {
-            Phy::new(self.phy.port + p, self.phy.rw).software_reset()?;
-        }
+        let port_status = self.phy.reset_port_pair();
 
         ////////////////////////////////////////////////////////////////////////
         // "Bug# 19146
@@ -101,7 +101,7 @@ impl<'a, 'b, P: PhyRw> Vsc8562Phy<'a, 'b, P> {
 
         // In the SDK, there's more configuration for 100BT, which we don't use
 
-        Ok(())
+        Ok(port_status)
     }
 
     /// `vtss_phy_chk_serdes_patch_init_private`
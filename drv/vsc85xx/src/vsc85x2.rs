@@ -2,8 +2,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{Counter, Phy, PhyRw, VscError};
-use vsc7448_pac::phy;
+use crate::{Counter, ErrorCounters, Phy, PhyRw, VscError};
+use vsc7448_pac::{phy, types::PhyRegisterAddress};
 
 // These IDs are (id1 << 16) | id2, meaning they also capture device revision
 // number.  This matters, because the patches are device-revision specific.
@@ -27,12 +27,23 @@ pub enum Vsc85x2Type {
 pub struct Vsc85x2 {
     base_port: u8,
     phy_type: Vsc85x2Type,
+    port_status: [Result<(), VscError>; 2],
 }
 
 impl Vsc85x2 {
+    /// Initializes the chip.  This returns an error if chip-wide
+    /// configuration fails; if only one of the two ports fails to come out of
+    /// reset, the chip is still returned, and the failure can be checked
+    /// with [Vsc85x2::port_status].
+    ///
+    /// `base_port + 1` must answer with the same PHY ID as `base_port`; if it
+    /// doesn't (e.g. because the board's strapping doesn't match
+    /// `base_port`), that's recorded as port 1's status, and port 0 is still
+    /// initialized.
     pub fn init<P: PhyRw>(base_port: u8, rw: &mut P) -> Result<Self, VscError> {
         let phy = &mut Phy::new(base_port, rw);
-        let phy_type = match phy.read_id()? {
+        let id = phy.read_id()?;
+        let phy_type = match id {
             VSC8552_ID => {
                 let rev = phy.read(phy::GPIO::EXTENDED_REVISION())?;
                 if rev.tesla_e() == 1 {
@@ -51,14 +62,34 @@ impl Vsc85x2 {
             }
             i => return Err(VscError::UnknownPhyId(i)),
         };
-        let out = Self {
+
+        // Port 1 should be the same chip at the next address up; if it isn't,
+        // `base_port` doesn't match the board's strapping, and port 1 can't be
+        // trusted (but port 0 may still be usable).
+        let next_id = match Phy::new(base_port + 1, rw).read_id() {
+            Ok(next) if next == id => Ok(()),
+            Ok(next) => Err(VscError::BadPhyId(next)),
+            Err(e) => Err(e),
+        };
+
+        let mut out = Self {
             base_port,
             phy_type,
+            port_status: [Ok(()); 2],
         };
-        out.phy(0, rw).init()?;
+        out.port_status = out.phy(0, rw).init()?;
+        if next_id.is_err() {
+            out.port_status[1] = next_id;
+        }
         Ok(out)
     }
 
+    /// Returns the result of initializing the given port, which must be
+    /// either 0 or 1.
+    pub fn port_status(&self, port: u8) -> Result<(), VscError> {
+        self.port_status[port as usize]
+    }
+
     /// Returns a handle to address the specified port, which must be either 0
     /// or 1; this function offsets by the chip's port offset, which is set
     /// by resistor strapping.
@@ -107,8 +138,9 @@ impl<'a, P: PhyRw> Vsc85x2Phy<'a, P> {
     /// pin.
     ///
     /// This must be called on the base port of the PHY; otherwise it will
-    /// return an error.
-    fn init(&mut self) -> Result<(), VscError> {
+    /// return an error.  On success, returns the result of resetting each of
+    /// the chip's two ports.
+    fn init(&mut self) -> Result<[Result<(), VscError>; 2], VscError> {
         match self.phy_type {
             Vsc85x2Type::Vsc8552 => {
                 crate::vsc8552::Vsc8552Phy { phy: &mut self.phy }.init()
@@ -154,6 +186,22 @@ impl<'a, P: PhyRw> Vsc85x2Phy<'a, P> {
         self.tx_rx_good()
     }
 
+    /// Reads the error counters in registers 19-21 of the main page.  These
+    /// clear when read, so each call reports the errors seen since the last.
+    pub fn error_counters(&mut self) -> Result<ErrorCounters, VscError> {
+        let mut read = |addr| -> Result<u8, VscError> {
+            let reg = PhyRegisterAddress::<u16>::from_page_and_addr_unchecked(
+                0, addr,
+            );
+            Ok(self.phy.read(reg)? as u8)
+        };
+        Ok(ErrorCounters {
+            rx_errors: read(19)?,
+            false_carrier: read(20)?,
+            link_disconnects: read(21)?,
+        })
+    }
+
     fn tx_rx_good(&mut self) -> Result<(Counter, Counter), VscError> {
         let r = self
             .phy
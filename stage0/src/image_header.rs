@@ -23,6 +23,36 @@ extern "C" {
 
 pub struct Image(&'static ImageVectors);
 
+/// How much of an image to check before trusting it
+#[derive(Copy, Clone, PartialEq)]
+pub enum Validation {
+    /// Check that the vector table and header are programmed and that the
+    /// header magic is correct. This is cheap, but won't catch an image whose
+    /// programming stopped partway through.
+    Header,
+    /// Additionally check the first page of the image past the header. This
+    /// costs one more page no matter the image size, and catches programming
+    /// that stopped right after the header was written.
+    FirstPage,
+    /// Additionally check that the whole image, as given by the header, is
+    /// programmed. This costs time proportional to the image size.
+    Full,
+}
+
+/// Why an image can't be used. The values are what `main` leaves in
+/// `IMAGE_ERROR`.
+#[derive(Copy, Clone, PartialEq)]
+#[repr(u32)]
+pub enum ImageError {
+    /// The slot is blank: the page holding the vector table was never
+    /// programmed, or was programmed with the erased pattern. We can tell
+    /// this quickly, without looking any further.
+    Empty = 1,
+    /// Something is programmed in the slot, but it isn't a complete, valid
+    /// image.
+    Invalid = 2,
+}
+
 // FLASH_PAGE_SIZE is a usize so redefine the constant here to avoid having
 // to do the u32 change everywhere
 const PAGE_SIZE: u32 = FLASH_PAGE_SIZE as u32;
@@ -34,7 +64,7 @@ const PAGE_SIZE: u32 = FLASH_PAGE_SIZE as u32;
 // It would technically be possible to create an instance of Image with an
 // invalid set of ImageVectors but that would require going far outside the
 // bounds of the expected design.
-pub fn get_image_a() -> Option<Image> {
+pub fn get_image_a(validation: Validation) -> Result<Image, ImageError> {
     // Safety: this is unsafe because `IMAGEA` is coming from
     // an extern, and might violate alignment rules or even be
     // modified externally and subject to data races. In our case
@@ -44,11 +74,9 @@ pub fn get_image_a() -> Option<Image> {
 
     let img = Image(imagea);
 
-    if !img.validate() {
-        return None;
-    }
+    img.validate(validation)?;
 
-    Some(img)
+    Ok(img)
 }
 
 impl Image {
@@ -65,15 +93,23 @@ impl Image {
         (self.get_img_start() + vector_size) as *const ImageHeader
     }
 
-    /// Make sure all of the image flash is programmed
-    fn validate(&self) -> bool {
+    /// Make sure the image flash is programmed, to the extent requested by
+    /// `validation`
+    fn validate(&self, validation: Validation) -> Result<(), ImageError> {
         let img_start = self.get_img_start();
 
-        // Start by making sure we can access the page where the vectors live
+        // Start by making sure we can access the page where the vectors live.
+        // If we can't, nothing was ever written here.
         let valid = lpc55_romapi::validate_programmed(img_start, PAGE_SIZE);
 
         if !valid {
-            return false;
+            return Err(ImageError::Empty);
+        }
+
+        // A slot programmed from a blank file is just as empty, and has a
+        // reset vector of all ones.
+        if self.0.entry == 0xffff_ffff {
+            return Err(ImageError::Empty);
         }
 
         let header_ptr = self.get_header();
@@ -83,7 +119,7 @@ impl Image {
             lpc55_romapi::validate_programmed(header_ptr as u32, PAGE_SIZE);
 
         if !valid {
-            return false;
+            return Err(ImageError::Invalid);
         }
 
         // SAFETY: We've validated the header location is programmed so this
@@ -91,22 +127,42 @@ impl Image {
         // which we trust.
         let header = unsafe { &*header_ptr };
 
-        // Next make sure the marked image length is programmed
-        let valid = lpc55_romapi::validate_programmed(
-            img_start,
-            (header.total_image_len + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1),
-        );
-
-        if !valid {
-            return false;
-        }
-
-        // Does this look correct?
+        // Does this look correct? Check before trusting the length below.
         if header.magic != abi::HEADER_MAGIC {
-            return false;
+            return Err(ImageError::Invalid);
         }
 
-        return true;
+        let img_end = img_start
+            + ((header.total_image_len + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1));
+
+        match validation {
+            Validation::Header => Ok(()),
+            Validation::FirstPage => {
+                // The page after the one the header ends in, unless the image
+                // is over by then (in which case we've already checked it).
+                let header_end = header_ptr as u32
+                    + core::mem::size_of::<ImageHeader>() as u32;
+                let page = (header_end + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1);
+                if page >= img_end
+                    || lpc55_romapi::validate_programmed(page, PAGE_SIZE)
+                {
+                    Ok(())
+                } else {
+                    Err(ImageError::Invalid)
+                }
+            }
+            Validation::Full => {
+                // Make sure the marked image length is programmed
+                if lpc55_romapi::validate_programmed(
+                    img_start,
+                    img_end - img_start,
+                ) {
+                    Ok(())
+                } else {
+                    Err(ImageError::Invalid)
+                }
+            }
+        }
     }
 
     pub fn get_vectors(&self) -> u32 {
@@ -17,7 +17,7 @@ use cortex_m_rt::entry;
 mod hypo;
 mod image_header;
 
-use crate::image_header::Image;
+use crate::image_header::{Image, Validation};
 
 /// Initial entry point for handling a memory management fault.
 #[allow(non_snake_case)]
@@ -53,22 +53,116 @@ const ROM_VER: u32 = 0;
 #[cfg(not(feature = "0A-hardware"))]
 const ROM_VER: u32 = 1;
 
+/// Returns the inclusive address range covered by an SAU entry, or `None` if
+/// the entry isn't enabled.
+#[cfg(feature = "tz_support")]
+fn sau_range(e: &abi::SAUEntry) -> Option<(u32, u32)> {
+    if e.rlar & 1 == 0 {
+        None
+    } else {
+        Some((e.rbar & !0x1f, e.rlar | 0x1f))
+    }
+}
+
+/// Makes sure the nonsecure regions requested by the image don't overlap
+/// each other or the secure hypo region. The regions come from the image
+/// header and the hypo region from our linker script, so a mistake in either
+/// would otherwise silently leave us with a broken (or exploitable) SAU
+/// configuration.
+#[cfg(feature = "tz_support")]
+fn check_sau_layout(image: &Image) {
+    extern "C" {
+        static address_of_start_flash_hypo: u32;
+        static address_of_end_flash_hypo: u32;
+    }
+
+    // SAFETY: these are generated by the linker script, which we trust
+    let (hypo_start, hypo_end) =
+        unsafe { (address_of_start_flash_hypo, address_of_end_flash_hypo) };
+
+    if hypo_end < hypo_start {
+        panic!("hypo region ends before it starts");
+    }
+
+    let overlaps = |a: (u32, u32), b: (u32, u32)| a.0 <= b.1 && b.0 <= a.1;
+
+    for i in 0..8 {
+        let a = match image.get_sau_entry(i).and_then(sau_range) {
+            Some(a) => a,
+            None => continue,
+        };
+
+        if hypo_end > hypo_start && overlaps(a, (hypo_start, hypo_end - 1)) {
+            panic!("nonsecure SAU region overlaps the hypo region");
+        }
+
+        for j in (i + 1)..8 {
+            if let Some(b) = image.get_sau_entry(j).and_then(sau_range) {
+                if overlaps(a, b) {
+                    panic!("nonsecure SAU regions overlap");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tz_support")]
+const SAU_CTRL: *mut u32 = 0xe000edd0 as *mut u32;
+#[cfg(feature = "tz_support")]
+const SAU_RNR: *mut u32 = 0xe000edd8 as *mut u32;
+#[cfg(feature = "tz_support")]
+const SAU_RBAR: *mut u32 = 0xe000eddc as *mut u32;
+#[cfg(feature = "tz_support")]
+const SAU_RLAR: *mut u32 = 0xe000ede0 as *mut u32;
+
+/// Reads back every SAU region we programmed, and the enable, panicking if
+/// the hardware doesn't hold what the image asked for. A region that silently
+/// failed to program would leave the secure/nonsecure boundary somewhere
+/// other than where we think it is, so we'd rather not boot at all.
+#[cfg(feature = "tz_support")]
+unsafe fn check_sau_programmed(image: &Image) {
+    // The low five bits of RBAR read as zero, as do RLAR[4:2]; only the
+    // address bits, NSC, and ENABLE are actually stored.
+    const RBAR_MASK: u32 = !0x1f;
+    const RLAR_MASK: u32 = !0x1c;
+
+    for i in 0..8 {
+        if let Some(r) = image.get_sau_entry(i) {
+            core::ptr::write_volatile(SAU_RNR, i as u32);
+            if core::ptr::read_volatile(SAU_RNR) != i as u32 {
+                panic!("SAU region number did not stick");
+            }
+
+            let rbar = core::ptr::read_volatile(SAU_RBAR);
+            let rlar = core::ptr::read_volatile(SAU_RLAR);
+            if rbar & RBAR_MASK != r.rbar & RBAR_MASK
+                || rlar & RLAR_MASK != r.rlar & RLAR_MASK
+            {
+                panic!("SAU region did not program correctly");
+            }
+        }
+    }
+
+    if core::ptr::read_volatile(SAU_CTRL) & 1 == 0 {
+        panic!("SAU is not enabled");
+    }
+}
+
 #[cfg(feature = "tz_support")]
 unsafe fn branch_to_image(image: Image) -> ! {
-    let sau_ctrl: *mut u32 = 0xe000edd0 as *mut u32;
-    let sau_rbar: *mut u32 = 0xe000eddc as *mut u32;
-    let sau_rlar: *mut u32 = 0xe000ede0 as *mut u32;
-    let sau_rnr: *mut u32 = 0xe000edd8 as *mut u32;
+    check_sau_layout(&image);
 
     for i in 0..8 {
         if let Some(r) = image.get_sau_entry(i) {
-            core::ptr::write_volatile(sau_rnr, i as u32);
-            core::ptr::write_volatile(sau_rbar, r.rbar);
-            core::ptr::write_volatile(sau_rlar, r.rlar);
+            core::ptr::write_volatile(SAU_RNR, i as u32);
+            core::ptr::write_volatile(SAU_RBAR, r.rbar);
+            core::ptr::write_volatile(SAU_RLAR, r.rlar);
         }
     }
 
-    core::ptr::write_volatile(sau_ctrl, 1);
+    core::ptr::write_volatile(SAU_CTRL, 1);
+
+    check_sau_programmed(&image);
 
     let mut peripherals = Peripherals::steal();
 
@@ -164,6 +258,15 @@ fn check_system_freq() {
     }
 }
 
+#[cfg(all(feature = "fast_boot", feature = "header_only_boot"))]
+compile_error!("fast_boot and header_only_boot are mutually exclusive");
+
+/// Why we couldn't boot image A, as an `ImageError`, or 0 if we haven't
+/// given up on it.
+#[used]
+#[no_mangle]
+static mut IMAGE_ERROR: u32 = 0;
+
 #[entry]
 fn main() -> ! {
     // This is the SYSCON_DIEID register on LPC55 which contains the ROM
@@ -176,9 +279,26 @@ fn main() -> ! {
 
     check_system_freq();
 
-    let imagea = match image_header::get_image_a() {
-        Some(a) => a,
-        None => panic!(),
+    // Checking the whole image costs boot time in proportion to its size;
+    // boards that need to boot fast can trade that for a check of the header
+    // and first page only, or of just the header.
+    let validation = if cfg!(feature = "header_only_boot") {
+        Validation::Header
+    } else if cfg!(feature = "fast_boot") {
+        Validation::FirstPage
+    } else {
+        Validation::Full
+    };
+    let imagea = match image_header::get_image_a(validation) {
+        Ok(a) => a,
+        Err(e) => {
+            // panic_halt throws away anything we'd say, so leave the reason
+            // where a debugger can find it.
+            unsafe {
+                core::ptr::addr_of_mut!(IMAGE_ERROR).write_volatile(e as u32)
+            };
+            panic!()
+        }
     };
 
     unsafe {
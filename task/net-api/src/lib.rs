@@ -16,6 +16,48 @@ pub enum NetError {
     QueueEmpty = 1,
     NotYours = 2,
     InvalidVLan = 3,
+    /// This board doesn't have the requested hardware
+    NotSupported = 4,
+    /// Communication with a management network device failed
+    DeviceError = 5,
+    /// The requested switch port does not exist
+    InvalidPort = 6,
+}
+
+/// Snapshot of the KSZ8463 switch's configuration registers, as returned by
+/// `ksz8463_dump`.  Per-port arrays are indexed by port number minus one.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct KszRegs {
+    /// Chip ID, with the enable bit masked off
+    pub id: u16,
+    pub enabled: bool,
+    /// SGCR1, 2, 3, 6, 7, 8, and 9, in that order
+    pub sgcr: [u16; 7],
+    pub cfgr: u16,
+    pub dsp_cntrl_6: u16,
+    pub port_cr1: [u16; 3],
+    pub port_cr2: [u16; 3],
+    pub port_vid: [u16; 3],
+}
+
+/// Error counters from one port of the VSC85x2 PHY, as returned by
+/// `read_phy_error_counters`.  Each saturates at 255, and is reset by reading
+/// it.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PhyErrorCounters {
+    pub rx_errors: u8,
+    pub false_carrier: u8,
+    pub link_disconnects: u8,
+}
+
+/// How the VSC85x2 PHY came up, as returned by `phy_status`.  If the chip as
+/// a whole didn't initialize, neither port is usable; otherwise, each port
+/// may have come up (or not) on its own.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PhyStatus {
+    pub chip_ok: bool,
+    /// Indexed by PHY port, 0 or 1
+    pub port_ok: [bool; 2],
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
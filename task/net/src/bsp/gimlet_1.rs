@@ -7,6 +7,7 @@ use drv_gimlet_seq_api::Sequencer;
 use drv_spi_api::Spi;
 use drv_stm32h7_eth as eth;
 use drv_stm32xx_sys_api::{Alternate, Port, Sys};
+use task_net_api::{KszRegs, NetError, PhyErrorCounters, PhyStatus};
 use userlib::{hl::sleep_for, task_slot};
 
 task_slot!(SPI, spi_driver);
@@ -67,6 +68,7 @@ impl Bsp {
                 ksz8463_nrst: Port::C.pin(2),
                 ksz8463_rst_type: mgmt::Ksz8463ResetSpeed::Normal,
                 ksz8463_vlan_mode: ksz8463::VLanMode::Optional,
+                ksz8463_switch_config: ksz8463::SwitchConfig::default(),
 
                 // SP_TO_MGMT_PHY_COMA_MODE
                 vsc85x2_coma_mode: Some(Port::D.pin(7)),
@@ -80,6 +82,38 @@ impl Bsp {
         )
     }
 
+    pub fn ksz8463_set_port_enabled(
+        &self,
+        port: u8,
+        enabled: bool,
+    ) -> Result<(), NetError> {
+        self.0.ksz8463_set_port_enabled(port, enabled)
+    }
+
+    pub fn ksz8463_port_enabled(&self, port: u8) -> Result<bool, NetError> {
+        self.0.ksz8463_port_enabled(port)
+    }
+
+    pub fn ksz8463_dump(&self) -> Result<KszRegs, NetError> {
+        self.0.ksz8463_dump()
+    }
+
+    pub fn ksz8463_flush_mac_table(&self) -> Result<(), NetError> {
+        self.0.ksz8463_flush_mac_table()
+    }
+
+    pub fn phy_error_counters(
+        &self,
+        eth: &eth::Ethernet,
+        port: u8,
+    ) -> Result<PhyErrorCounters, NetError> {
+        self.0.phy_error_counters(eth, port)
+    }
+
+    pub fn phy_status(&self) -> Result<PhyStatus, NetError> {
+        Ok(self.0.phy_status())
+    }
+
     pub fn wake(&self, eth: &eth::Ethernet) {
         self.0.wake(eth);
     }
@@ -11,6 +11,7 @@ use ksz8463::{
     Error as KszError, MIBCounter, MIBCounterValue, Register as KszRegister,
 };
 use ringbuf::*;
+use task_net_api::{KszRegs, NetError, PhyErrorCounters, PhyStatus};
 use userlib::task_slot;
 use vsc7448_pac::{phy, types::PhyRegisterAddress};
 use vsc85xx::VscError;
@@ -132,6 +133,7 @@ impl Bsp {
             ksz8463_nrst: Port::A.pin(9),
             ksz8463_rst_type: mgmt::Ksz8463ResetSpeed::Slow,
             ksz8463_vlan_mode: ksz8463::VLanMode::Optional,
+            ksz8463_switch_config: ksz8463::SwitchConfig::default(),
 
             vsc85x2_coma_mode: None,
             vsc85x2_nrst: Port::A.pin(10),
@@ -146,6 +148,38 @@ impl Bsp {
         Self { mgmt, leds }
     }
 
+    pub fn ksz8463_set_port_enabled(
+        &self,
+        port: u8,
+        enabled: bool,
+    ) -> Result<(), NetError> {
+        self.mgmt.ksz8463_set_port_enabled(port, enabled)
+    }
+
+    pub fn ksz8463_port_enabled(&self, port: u8) -> Result<bool, NetError> {
+        self.mgmt.ksz8463_port_enabled(port)
+    }
+
+    pub fn ksz8463_dump(&self) -> Result<KszRegs, NetError> {
+        self.mgmt.ksz8463_dump()
+    }
+
+    pub fn ksz8463_flush_mac_table(&self) -> Result<(), NetError> {
+        self.mgmt.ksz8463_flush_mac_table()
+    }
+
+    pub fn phy_error_counters(
+        &self,
+        eth: &eth::Ethernet,
+        port: u8,
+    ) -> Result<PhyErrorCounters, NetError> {
+        self.mgmt.phy_error_counters(eth, port)
+    }
+
+    pub fn phy_status(&self) -> Result<PhyStatus, NetError> {
+        Ok(self.mgmt.phy_status())
+    }
+
     pub fn wake(&self, eth: &eth::Ethernet) {
         // Run the BSP wake function, which logs summarized data to a different
         // ringbuf; we'll still do verbose logging of full registers below.
@@ -188,7 +222,12 @@ impl Bsp {
         let mut any_link = false;
         let rw = &mut MiimBridge::new(eth);
         for i in [0, 1] {
-            let mut phy = self.mgmt.vsc85x2.phy(i, rw).phy;
+            // A port (or whole chip) that didn't come up won't answer
+            let vsc85x2 = match &self.mgmt.vsc85x2 {
+                Some(vsc85x2) if vsc85x2.port_status(i).is_ok() => vsc85x2,
+                _ => continue,
+            };
+            let mut phy = vsc85x2.phy(i, rw).phy;
             let port = phy.port;
 
             ringbuf_entry!(match phy.read(phy::STANDARD::MODE_STATUS()) {
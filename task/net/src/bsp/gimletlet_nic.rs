@@ -11,6 +11,7 @@ use ksz8463::{
     Register as KszRegister,
 };
 use ringbuf::*;
+use task_net_api::{KszRegs, NetError, PhyErrorCounters, PhyStatus};
 use userlib::{hl::sleep_for, task_slot};
 
 task_slot!(SPI, spi_driver);
@@ -79,6 +80,38 @@ impl Bsp {
         Self { ksz8463 }
     }
 
+    pub fn ksz8463_set_port_enabled(
+        &self,
+        port: u8,
+        enabled: bool,
+    ) -> Result<(), NetError> {
+        crate::ksz::ksz8463_set_port_enabled(&self.ksz8463, port, enabled)
+    }
+
+    pub fn ksz8463_port_enabled(&self, port: u8) -> Result<bool, NetError> {
+        crate::ksz::ksz8463_port_enabled(&self.ksz8463, port)
+    }
+
+    pub fn ksz8463_dump(&self) -> Result<KszRegs, NetError> {
+        crate::ksz::ksz8463_dump(&self.ksz8463)
+    }
+
+    pub fn ksz8463_flush_mac_table(&self) -> Result<(), NetError> {
+        crate::ksz::ksz8463_flush_mac_table(&self.ksz8463)
+    }
+
+    pub fn phy_error_counters(
+        &self,
+        _eth: &eth::Ethernet,
+        _port: u8,
+    ) -> Result<PhyErrorCounters, NetError> {
+        Err(NetError::NotSupported)
+    }
+
+    pub fn phy_status(&self) -> Result<PhyStatus, NetError> {
+        Err(NetError::NotSupported)
+    }
+
     pub fn wake(&self, _eth: &eth::Ethernet) {
         for port in [1, 2] {
             ringbuf_entry!(
@@ -5,6 +5,7 @@
 use crate::pins;
 use drv_stm32h7_eth as eth;
 use drv_stm32xx_sys_api::{Alternate, Port, Sys};
+use task_net_api::{KszRegs, NetError, PhyErrorCounters, PhyStatus};
 
 /// Address used on the MDIO link by our Ethernet PHY. Different
 /// vendors have different defaults for this, it will likely need to
@@ -66,6 +67,38 @@ impl Bsp {
         Self {}
     }
 
+    pub fn ksz8463_set_port_enabled(
+        &self,
+        _port: u8,
+        _enabled: bool,
+    ) -> Result<(), NetError> {
+        Err(NetError::NotSupported)
+    }
+
+    pub fn ksz8463_port_enabled(&self, _port: u8) -> Result<bool, NetError> {
+        Err(NetError::NotSupported)
+    }
+
+    pub fn ksz8463_dump(&self) -> Result<KszRegs, NetError> {
+        Err(NetError::NotSupported)
+    }
+
+    pub fn ksz8463_flush_mac_table(&self) -> Result<(), NetError> {
+        Err(NetError::NotSupported)
+    }
+
+    pub fn phy_error_counters(
+        &self,
+        _eth: &eth::Ethernet,
+        _port: u8,
+    ) -> Result<PhyErrorCounters, NetError> {
+        Err(NetError::NotSupported)
+    }
+
+    pub fn phy_status(&self) -> Result<PhyStatus, NetError> {
+        Err(NetError::NotSupported)
+    }
+
     pub fn wake(&self, _eth: &eth::Ethernet) {
         panic!("Wake should never be called, because WAKE_INTERVAL is None");
     }
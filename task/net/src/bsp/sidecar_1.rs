@@ -2,11 +2,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{mgmt, miim_bridge::MiimBridge, pins};
+use crate::{mgmt, pins};
 use drv_sidecar_seq_api::Sequencer;
 use drv_spi_api::Spi;
 use drv_stm32h7_eth as eth;
 use drv_stm32xx_sys_api::{Alternate, Port, Sys};
+use task_net_api::{KszRegs, NetError, PhyErrorCounters, PhyStatus};
 use userlib::{hl::sleep_for, task_slot};
 
 task_slot!(SPI, spi_driver);
@@ -65,6 +66,7 @@ impl Bsp {
             ksz8463_nrst: Port::A.pin(0),
             ksz8463_rst_type: mgmt::Ksz8463ResetSpeed::Normal,
             ksz8463_vlan_mode: ksz8463::VLanMode::Optional,
+            ksz8463_switch_config: ksz8463::SwitchConfig::default(),
 
             // SP_TO_PHY2_COMA_MODE_3V3
             vsc85x2_coma_mode: Some(Port::I.pin(15)),
@@ -76,12 +78,43 @@ impl Bsp {
 
         // The VSC8552 on the sidecar has its SIGDET GPIOs pulled down,
         // for some reason.
-        let rw = &mut MiimBridge::new(eth);
-        bsp.vsc85x2.set_sigdet_polarity(rw, true).unwrap();
+        bsp.vsc85x2_set_sigdet_polarity(eth, true);
 
         Self(bsp)
     }
 
+    pub fn ksz8463_set_port_enabled(
+        &self,
+        port: u8,
+        enabled: bool,
+    ) -> Result<(), NetError> {
+        self.0.ksz8463_set_port_enabled(port, enabled)
+    }
+
+    pub fn ksz8463_port_enabled(&self, port: u8) -> Result<bool, NetError> {
+        self.0.ksz8463_port_enabled(port)
+    }
+
+    pub fn ksz8463_dump(&self) -> Result<KszRegs, NetError> {
+        self.0.ksz8463_dump()
+    }
+
+    pub fn ksz8463_flush_mac_table(&self) -> Result<(), NetError> {
+        self.0.ksz8463_flush_mac_table()
+    }
+
+    pub fn phy_error_counters(
+        &self,
+        eth: &eth::Ethernet,
+        port: u8,
+    ) -> Result<PhyErrorCounters, NetError> {
+        self.0.phy_error_counters(eth, port)
+    }
+
+    pub fn phy_status(&self) -> Result<PhyStatus, NetError> {
+        Ok(self.0.phy_status())
+    }
+
     pub fn wake(&self, eth: &eth::Ethernet) {
         self.0.wake(eth);
     }
@@ -0,0 +1,84 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! KSZ8463 operations exposed over IPC, shared by every BSP which has one.
+
+use ksz8463::{Error as KszError, Ksz8463, Register};
+use task_net_api::{KszRegs, NetError};
+
+/// Reads the configuration registers of the KSZ8463, for diffing against
+/// what we expect to have written.
+pub fn ksz8463_dump(ksz: &Ksz8463) -> Result<KszRegs, NetError> {
+    read_regs(ksz).map_err(|_| NetError::DeviceError)
+}
+
+/// Enables or disables a port, which must be 1, 2, or 3.
+pub fn ksz8463_set_port_enabled(
+    ksz: &Ksz8463,
+    port: u8,
+    enabled: bool,
+) -> Result<(), NetError> {
+    check_port(port)?;
+    ksz.set_port_enabled(port, enabled)
+        .map_err(|_| NetError::DeviceError)
+}
+
+/// Checks whether a port, which must be 1, 2, or 3, is enabled.
+pub fn ksz8463_port_enabled(ksz: &Ksz8463, port: u8) -> Result<bool, NetError> {
+    check_port(port)?;
+    ksz.port_enabled(port).map_err(|_| NetError::DeviceError)
+}
+
+/// Flushes the dynamic MAC table, forcing the switch to re-learn addresses.
+pub fn ksz8463_flush_mac_table(ksz: &Ksz8463) -> Result<(), NetError> {
+    ksz.flush_dynamic_mac_table()
+        .map_err(|_| NetError::DeviceError)
+}
+
+/// The driver panics on invalid ports, so check them here first, since this
+/// value comes straight from an IPC caller.
+fn check_port(port: u8) -> Result<(), NetError> {
+    if (1..=3).contains(&port) {
+        Ok(())
+    } else {
+        Err(NetError::InvalidPort)
+    }
+}
+
+fn read_regs(ksz: &Ksz8463) -> Result<KszRegs, KszError> {
+    let mut out = KszRegs::default();
+
+    let cider = ksz.read(Register::CIDER)?;
+    out.id = cider & !1;
+    out.enabled = cider & 1 != 0;
+
+    let sgcr = [
+        Register::SGCR1,
+        Register::SGCR2,
+        Register::SGCR3,
+        Register::SGCR6,
+        Register::SGCR7,
+        Register::SGCR8,
+        Register::SGCR9,
+    ];
+    for (r, v) in sgcr.into_iter().zip(out.sgcr.iter_mut()) {
+        *v = ksz.read(r)?;
+    }
+
+    out.cfgr = ksz.read(Register::CFGR)?;
+    out.dsp_cntrl_6 = ksz.read(Register::DSP_CNTRL_6)?;
+
+    let ports = [
+        (Register::P1CR1, Register::P1CR2, Register::P1VIDCR),
+        (Register::P2CR1, Register::P2CR2, Register::P2VIDCR),
+        (Register::P3CR1, Register::P3CR2, Register::P3VIDCR),
+    ];
+    for (i, (cr1, cr2, vid)) in ports.into_iter().enumerate() {
+        out.port_cr1[i] = ksz.read(cr1)?;
+        out.port_cr2[i] = ksz.read(cr2)?;
+        out.port_vid[i] = ksz.read(vid)?;
+    }
+
+    Ok(out)
+}
@@ -7,6 +7,7 @@
 
 mod bsp;
 mod buf;
+mod smi;
 
 pub mod pins;
 
@@ -27,8 +28,13 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(feature = "ksz8463")]
+mod ksz;
+
 mod idl {
-    use task_net_api::{NetError, SocketName, UdpMetadata};
+    use task_net_api::{
+        KszRegs, NetError, PhyErrorCounters, PhyStatus, SocketName, UdpMetadata,
+    };
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
 
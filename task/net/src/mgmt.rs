@@ -8,6 +8,7 @@ use drv_stm32h7_eth::Ethernet;
 use drv_stm32xx_sys_api::{self as sys_api, OutputType, Pull, Speed, Sys};
 use ksz8463::{Error as KszError, Ksz8463, Register as KszRegister};
 use ringbuf::*;
+use task_net_api::{KszRegs, NetError, PhyErrorCounters, PhyStatus};
 use userlib::hl::sleep_for;
 use vsc7448_pac::phy;
 use vsc85xx::{vsc85x2::Vsc85x2, Counter, VscError};
@@ -30,6 +31,7 @@ pub struct Status {
     ksz8463_rx_bytes: [ksz8463::MIBCounterValue; 2],
     ksz8463_tx_bytes: [ksz8463::MIBCounterValue; 2],
 
+    vsc85x2_port_ok: [bool; 2],
     vsc85x2_100base_fx_link_up: [bool; 2],
     vsc85x2_sgmii_link_up: [bool; 2],
 
@@ -46,11 +48,38 @@ enum Trace {
     None,
     Ksz8463Err { port: u8, err: KszError },
     Vsc85x2Err { port: u8, err: VscError },
+    Vsc85x2InitFailed(VscError),
+    Vsc85x2SigdetFailed(VscError),
     Status(Status),
+    PllLockTimeout,
+    PowerGoodTimeout,
 }
 
 ringbuf!(Trace, 16, Trace::None);
 
+/// How long to hold the power enable low when power-cycling the PHY, in ms
+const POWER_EN_LOW_MS: u32 = 10;
+
+/// How long to wait for the PHY to come out of reset, in ms
+const PHY_RESET_MS: u64 = 120;
+
+/// How long to wait for the PLL lock and power good pins, in ms.  If they
+/// don't assert in that time, we log it and carry on regardless.
+const PIN_WAIT_TIMEOUT_MS: u64 = 100;
+
+/// Polls until every pin in `pins` reads high, returning `false` if that
+/// doesn't happen within `PIN_WAIT_TIMEOUT_MS`.
+fn wait_for_pins(sys: &Sys, pins: sys_api::PinSet) -> bool {
+    sys.gpio_configure_input(pins, Pull::None).unwrap();
+    for _ in 0..PIN_WAIT_TIMEOUT_MS {
+        if sys.gpio_read(pins).unwrap() == pins.pin_mask {
+            return true;
+        }
+        sleep_for(1);
+    }
+    false
+}
+
 /// Configuration struct for the rest of the management network hardware,
 /// which is a KSZ8463 switch attached to a VSC8552 or VSC8562 PHY.
 pub struct Config {
@@ -72,9 +101,12 @@ pub struct Config {
     pub ksz8463_nrst: sys_api::PinSet,
     pub ksz8463_rst_type: Ksz8463ResetSpeed,
     pub ksz8463_vlan_mode: ksz8463::VLanMode,
+    pub ksz8463_switch_config: ksz8463::SwitchConfig,
 
     pub vsc85x2_coma_mode: Option<sys_api::PinSet>,
     pub vsc85x2_nrst: sys_api::PinSet,
+    /// MIIM address of the VSC85x2's port 0, which is set by resistor
+    /// strapping; port 1 is at the next address up.
     pub vsc85x2_base_port: u8,
 }
 
@@ -111,14 +143,23 @@ impl Config {
 
         // The KSZ8463 connects to the SP over RMII, then sends data to the
         // VSC8552 over 100-BASE FX
+        // A warm SP restart can leave the switch in a state that it doesn't
+        // leave on its own, so soft-reset and try again if it won't enable.
         ksz8463
-            .configure(ksz8463::Mode::Fiber, self.ksz8463_vlan_mode)
+            .configure_with_retry(ksz8463::Mode::Fiber, self.ksz8463_vlan_mode)
+            .unwrap();
+        ksz8463
+            .apply_switch_config(&self.ksz8463_switch_config)
             .unwrap();
         ksz8463
     }
 
-    fn configure_vsc85x2(&self, sys: &Sys, eth: &Ethernet) -> Vsc85x2 {
-        // TODO: wait for PLL lock to happen here
+    fn configure_vsc85x2(&self, sys: &Sys, eth: &Ethernet) -> Option<Vsc85x2> {
+        if let Some(pll_lock) = self.pll_lock {
+            if !wait_for_pins(sys, pll_lock) {
+                ringbuf_entry!(Trace::PllLockTimeout);
+            }
+        }
 
         // Start with reset low and COMA_MODE high
         sys.gpio_reset(self.vsc85x2_nrst).unwrap();
@@ -145,8 +186,7 @@ impl Config {
         if let Some(power_en) = self.power_en {
             sys.gpio_init_reset_pulse(
                 power_en,
-                // TODO: how long does this need to be?
-                10,
+                POWER_EN_LOW_MS,
                 // Certain boards have longer startup times than others.
                 // See hardware-psc/issues/48 for analysis; it appears to
                 // be an issue with the level shifter rise times.
@@ -155,10 +195,14 @@ impl Config {
             .unwrap();
         }
 
-        // TODO: sleep for PG lines going high here
+        if let Some(power_good) = self.power_good {
+            if !wait_for_pins(sys, power_good) {
+                ringbuf_entry!(Trace::PowerGoodTimeout);
+            }
+        }
 
         sys.gpio_set(self.vsc85x2_nrst).unwrap();
-        sleep_for(120); // Wait for the chip to come out of reset
+        sleep_for(PHY_RESET_MS); // Wait for the chip to come out of reset
 
         // Build handle for the VSC85x2 PHY, then initialize it
         let rw = &mut MiimBridge::new(eth);
@@ -169,16 +213,108 @@ impl Config {
             sys.gpio_reset(coma_mode).unwrap();
         }
 
-        vsc85x2.unwrap() // TODO
+        // If the chip won't initialize at all, we carry on without it (the
+        // KSZ8463 still works, and we can report what happened); if only one
+        // port failed, keep going with the other one: partial connectivity is
+        // better than none.
+        let vsc85x2 = match vsc85x2 {
+            Ok(vsc85x2) => vsc85x2,
+            Err(err) => {
+                ringbuf_entry!(Trace::Vsc85x2InitFailed(err));
+                return None;
+            }
+        };
+        for port in 0..2 {
+            if let Err(err) = vsc85x2.port_status(port) {
+                ringbuf_entry!(Trace::Vsc85x2Err { port, err });
+            }
+        }
+
+        Some(vsc85x2)
     }
 }
 
 pub struct Bsp {
     pub ksz8463: Ksz8463,
-    pub vsc85x2: Vsc85x2,
+    /// `None` if the chip failed to initialize
+    pub vsc85x2: Option<Vsc85x2>,
 }
 
 impl Bsp {
+    pub fn ksz8463_set_port_enabled(
+        &self,
+        port: u8,
+        enabled: bool,
+    ) -> Result<(), NetError> {
+        crate::ksz::ksz8463_set_port_enabled(&self.ksz8463, port, enabled)
+    }
+
+    pub fn ksz8463_port_enabled(&self, port: u8) -> Result<bool, NetError> {
+        crate::ksz::ksz8463_port_enabled(&self.ksz8463, port)
+    }
+
+    pub fn ksz8463_dump(&self) -> Result<KszRegs, NetError> {
+        crate::ksz::ksz8463_dump(&self.ksz8463)
+    }
+
+    pub fn ksz8463_flush_mac_table(&self) -> Result<(), NetError> {
+        crate::ksz::ksz8463_flush_mac_table(&self.ksz8463)
+    }
+
+    pub fn phy_status(&self) -> PhyStatus {
+        let mut s = PhyStatus::default();
+        if let Some(vsc85x2) = &self.vsc85x2 {
+            s.chip_ok = true;
+            for port in 0..2 {
+                s.port_ok[port] = vsc85x2.port_status(port as u8).is_ok();
+            }
+        }
+        s
+    }
+
+    /// Sets the SIGDET polarity of the VSC85x2's ports (by default, active
+    /// high), if the chip came up.
+    pub fn vsc85x2_set_sigdet_polarity(
+        &self,
+        eth: &Ethernet,
+        active_low: bool,
+    ) {
+        if let Some(vsc85x2) = &self.vsc85x2 {
+            let rw = &mut MiimBridge::new(eth);
+            if let Err(err) = vsc85x2.set_sigdet_polarity(rw, active_low) {
+                ringbuf_entry!(Trace::Vsc85x2SigdetFailed(err));
+            }
+        }
+    }
+
+    /// Reads (and so clears) the error counters of a VSC85x2 port, which
+    /// must be 0 or 1.
+    pub fn phy_error_counters(
+        &self,
+        eth: &Ethernet,
+        port: u8,
+    ) -> Result<PhyErrorCounters, NetError> {
+        if port >= 2 {
+            return Err(NetError::InvalidPort);
+        }
+        // A port which didn't come out of reset (or whose chip didn't
+        // initialize) won't answer
+        let vsc85x2 = match &self.vsc85x2 {
+            Some(vsc85x2) if vsc85x2.port_status(port).is_ok() => vsc85x2,
+            _ => return Err(NetError::DeviceError),
+        };
+        let rw = &mut MiimBridge::new(eth);
+        let c = vsc85x2.phy(port, rw).error_counters().map_err(|err| {
+            ringbuf_entry!(Trace::Vsc85x2Err { port, err });
+            NetError::DeviceError
+        })?;
+        Ok(PhyErrorCounters {
+            rx_errors: c.rx_errors,
+            false_carrier: c.false_carrier,
+            link_disconnects: c.link_disconnects,
+        })
+    }
+
     pub fn wake(&self, eth: &Ethernet) {
         let mut s = Status::default();
         let rw = &mut MiimBridge::new(eth);
@@ -214,7 +350,12 @@ impl Bsp {
 
             // The VSC85x2 numbers its ports starting at 0
             let port = i as u8;
-            let mut phy = self.vsc85x2.phy(port, rw);
+            let vsc85x2 = match &self.vsc85x2 {
+                Some(vsc85x2) if vsc85x2.port_status(port).is_ok() => vsc85x2,
+                _ => continue,
+            };
+            s.vsc85x2_port_ok[i] = true;
+            let mut phy = vsc85x2.phy(port, rw);
             match phy.phy.read(phy::STANDARD::MODE_STATUS()) {
                 Ok(sr) => {
                     s.vsc85x2_100base_fx_link_up[i] = (sr.0 & (1 << 2)) != 0
@@ -0,0 +1,29 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! SMI operations exposed over IPC, shared by the VLAN and non-VLAN servers.
+
+use drv_stm32h7_eth as eth;
+use idol_runtime::{ClientError, RequestError};
+use task_net_api::NetError;
+
+/// Reads `count` consecutive registers from `phy`, starting at `start`, into
+/// the front of the returned array.
+pub fn smi_read_range(
+    eth: &eth::Ethernet,
+    phy: u8,
+    start: u8,
+    count: u8,
+) -> Result<[u16; 32], RequestError<NetError>> {
+    // Clause 22 only has 32 registers per page, so that's as many as we can
+    // read in one go.
+    if usize::from(start) + usize::from(count) > 32 {
+        return Err(RequestError::Fail(ClientError::BadMessageContents));
+    }
+    let mut out = [0; 32];
+    for (v, register) in out.iter_mut().zip(start..start + count) {
+        *v = eth.smi_read(phy, register);
+    }
+    Ok(out)
+}
@@ -17,7 +17,9 @@ use smoltcp::socket::UdpSocket;
 use smoltcp::wire::{
     EthernetAddress, IpAddress, IpCidr, Ipv6Address, Ipv6Cidr,
 };
-use task_net_api::{NetError, SocketName, UdpMetadata};
+use task_net_api::{
+    KszRegs, NetError, PhyErrorCounters, PhyStatus, SocketName, UdpMetadata,
+};
 use userlib::{sys_post, sys_refresh_task_id};
 
 use crate::generated::{self, SOCKET_COUNT, VLAN_COUNT, VLAN_RANGE};
@@ -376,6 +378,17 @@ impl idl::InOrderNetImpl for ServerImpl<'_> {
         Ok(self.eth.smi_read(phy, register))
     }
 
+    fn smi_read_range(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        phy: u8,
+        start: u8,
+        count: u8,
+    ) -> Result<[u16; 32], RequestError<NetError>> {
+        // TODO: this should not be open to all callers!
+        crate::smi::smi_read_range(self.eth, phy, start, count)
+    }
+
     fn smi_write(
         &mut self,
         _msg: &userlib::RecvMessage,
@@ -386,6 +399,71 @@ impl idl::InOrderNetImpl for ServerImpl<'_> {
         // TODO: this should not be open to all callers!
         Ok(self.eth.smi_write(phy, register, value))
     }
+
+    fn ksz8463_set_port_enabled(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        port: u8,
+        enabled: u8,
+    ) -> Result<(), RequestError<NetError>> {
+        self.bsp
+            .ksz8463_set_port_enabled(port, enabled != 0)
+            .map_err(Into::into)
+    }
+
+    fn ksz8463_port_enabled(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        port: u8,
+    ) -> Result<u8, RequestError<NetError>> {
+        self.bsp
+            .ksz8463_port_enabled(port)
+            .map(u8::from)
+            .map_err(Into::into)
+    }
+
+    fn ksz8463_dump(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<KszRegs, RequestError<NetError>> {
+        self.bsp.ksz8463_dump().map_err(Into::into)
+    }
+
+    fn ksz8463_flush_mac_table(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<(), RequestError<NetError>> {
+        self.bsp.ksz8463_flush_mac_table().map_err(Into::into)
+    }
+
+    fn read_phy_error_counters(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        port: u8,
+    ) -> Result<PhyErrorCounters, RequestError<NetError>> {
+        self.bsp
+            .phy_error_counters(&self.eth, port)
+            .map_err(Into::into)
+    }
+
+    fn clear_phy_error_counters(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        port: u8,
+    ) -> Result<(), RequestError<NetError>> {
+        // The counters clear on read, so reading them is all it takes.
+        self.bsp
+            .phy_error_counters(&self.eth, port)
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    fn phy_status(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<PhyStatus, RequestError<NetError>> {
+        self.bsp.phy_status().map_err(Into::into)
+    }
 }
 
 impl NotificationHandler for ServerImpl<'_> {